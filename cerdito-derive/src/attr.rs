@@ -0,0 +1,370 @@
+//! Parsing of `#[cerdito(...)]` attributes.
+
+use quote::ToTokens;
+
+const INT_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+];
+
+/// Attributes of a struct or enum.
+#[derive(Default)]
+pub struct ContainerAttrs {
+    /// `#[cerdito(transparent)]`: a single-field struct is encoded as its field.
+    pub transparent: bool,
+    /// `#[cerdito(rename = "name")]`: name passed to the encoder and decoder hooks.
+    pub rename: Option<String>,
+    /// `#[cerdito(rename_all = "rule")]`: renames struct fields or enum variants.
+    pub rename_all: Option<RenameRule>,
+    /// `#[cerdito(bound = "...")]` or `#[cerdito(bound(encode = "..."))]`: where clause of the
+    /// `Encode` impl, replaces the inferred bounds.
+    pub encode_bound: Option<Vec<syn::WherePredicate>>,
+    /// `#[cerdito(bound = "...")]` or `#[cerdito(bound(decode = "..."))]`: where clause of the
+    /// `Decode` impl, replaces the inferred bounds.
+    pub decode_bound: Option<Vec<syn::WherePredicate>>,
+    /// `#[cerdito(crate = "path")]`: path of the `cerdito` crate, e.g. a re-export.
+    pub krate: Option<syn::Path>,
+    /// `#[repr(u8)]`, `#[repr(i64)]`, ...: type of the enum discriminants.
+    pub repr: Option<syn::Ident>,
+}
+
+impl ContainerAttrs {
+    pub fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("repr")) {
+            let metas = attr.parse_args_with(
+                syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated,
+            )?;
+            for meta in metas {
+                match meta.path().get_ident() {
+                    Some(ident) if INT_TYPES.contains(&ident.to_string().as_str()) => {
+                        result.repr = Some(ident.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for attr in attrs.iter().filter(|a| a.path().is_ident("cerdito")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("transparent") {
+                    result.transparent = true;
+                } else if meta.path.is_ident("rename") {
+                    set_once(&meta, &mut result.rename, parse_name(&meta)?)?;
+                } else if meta.path.is_ident("rename_all") {
+                    set_once(&meta, &mut result.rename_all, parse_rule(&meta)?)?;
+                } else if meta.path.is_ident("crate") {
+                    let lit: syn::LitStr = meta.value()?.parse()?;
+                    set_once(&meta, &mut result.krate, lit.parse()?)?;
+                } else if meta.path.is_ident("bound") && meta.input.peek(syn::Token![=]) {
+                    let bound = parse_bound(&meta)?;
+                    set_once(&meta, &mut result.encode_bound, bound.clone())?;
+                    set_once(&meta, &mut result.decode_bound, bound)?;
+                } else if meta.path.is_ident("bound") {
+                    meta.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("encode") {
+                            set_once(&meta, &mut result.encode_bound, parse_bound(&meta)?)
+                        } else if meta.path.is_ident("decode") {
+                            set_once(&meta, &mut result.decode_bound, parse_bound(&meta)?)
+                        } else {
+                            Err(meta.error("expected `encode` or `decode`"))
+                        }
+                    })?;
+                } else {
+                    return Err(meta.error("unsupported cerdito container attribute"));
+                }
+                if result.transparent && (result.rename.is_some() || result.rename_all.is_some()) {
+                    return Err(meta.error("`transparent` struct can't be renamed"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(result)
+    }
+
+    /// Path of the `cerdito` crate.
+    pub fn krate(&self) -> syn::Path {
+        self.krate
+            .clone()
+            .unwrap_or_else(|| syn::parse_quote!(::cerdito))
+    }
+
+    /// Name of the struct or enum passed to the hooks.
+    pub fn name(&self, ident: &syn::Ident) -> String {
+        rename(ident, self.rename.as_deref(), None)
+    }
+}
+
+/// Attributes of an enum variant.
+#[derive(Default)]
+pub struct VariantAttrs {
+    /// `#[cerdito(tag = N)]`: wire tag of the variant, overrides the Rust discriminant.
+    pub tag: Option<syn::Expr>,
+    /// `#[cerdito(alias_tags = [N, ...])]`: legacy tags also accepted on decode.
+    pub alias_tags: Vec<syn::Expr>,
+    /// `#[cerdito(rename = "name")]`: variant name passed to the hooks.
+    pub rename: Option<String>,
+    /// `#[cerdito(rename_all = "rule")]`: renames the variant fields.
+    pub rename_all: Option<RenameRule>,
+}
+
+impl VariantAttrs {
+    pub fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("cerdito")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("tag") {
+                    set_once(&meta, &mut result.tag, meta.value()?.parse()?)?;
+                } else if meta.path.is_ident("alias_tags") {
+                    let value = meta.value()?;
+                    let content;
+                    syn::bracketed!(content in value);
+                    let tags = content.parse_terminated(
+                        <syn::Expr as syn::parse::Parse>::parse,
+                        syn::Token![,],
+                    )?;
+                    result.alias_tags.extend(tags);
+                } else if meta.path.is_ident("rename") {
+                    set_once(&meta, &mut result.rename, parse_name(&meta)?)?;
+                } else if meta.path.is_ident("rename_all") {
+                    set_once(&meta, &mut result.rename_all, parse_rule(&meta)?)?;
+                } else {
+                    return Err(meta.error("unsupported cerdito variant attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(result)
+    }
+
+    /// Name of the variant passed to the hooks, `rename_all` is the rule of the enum.
+    pub fn name(&self, ident: &syn::Ident, rename_all: Option<RenameRule>) -> String {
+        rename(ident, self.rename.as_deref(), rename_all)
+    }
+}
+
+/// Attributes of a struct or enum variant field.
+#[derive(Default)]
+pub struct FieldAttrs {
+    /// `#[cerdito(default = expr)]`: value of the field when it is missing in old data.
+    pub default: Option<syn::Expr>,
+    /// `#[cerdito(required)]`: fail with a missing field error instead of defaulting.
+    pub required: bool,
+    /// `#[cerdito(skip_encoding)]` or `#[cerdito(skip)]`: the field is not written.
+    pub skip_encoding: bool,
+    /// `#[cerdito(skip_decoding)]` or `#[cerdito(skip)]`: the field is not read and is
+    /// filled like a missing field.
+    pub skip_decoding: bool,
+    /// `#[cerdito(encode_with = path)]` or `#[cerdito(with = module)]`: function used instead of
+    /// `Encode::encode`, with `_async` counterpart.
+    pub encode_with: Option<syn::Path>,
+    /// `#[cerdito(decode_with = path)]` or `#[cerdito(with = module)]`: function used instead of
+    /// `Decode::decode`, with `_async` counterpart.
+    pub decode_with: Option<syn::Path>,
+    /// `#[cerdito(rename = "name")]`: field name passed to the hooks.
+    pub rename: Option<String>,
+    /// `#[cerdito(boxed)]`: the async encode and decode futures of the field are boxed.
+    pub boxed: bool,
+}
+
+impl FieldAttrs {
+    pub fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut result = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("cerdito")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("default") {
+                    set_once(&meta, &mut result.default, meta.value()?.parse()?)?;
+                } else if meta.path.is_ident("required") {
+                    result.required = true;
+                } else if meta.path.is_ident("skip") {
+                    result.skip_encoding = true;
+                    result.skip_decoding = true;
+                } else if meta.path.is_ident("skip_encoding") {
+                    result.skip_encoding = true;
+                } else if meta.path.is_ident("skip_decoding") {
+                    result.skip_decoding = true;
+                } else if meta.path.is_ident("with") {
+                    if result.encode_with.is_some() || result.decode_with.is_some() {
+                        return Err(meta.error(
+                            "`with` can't be used together with `encode_with` or `decode_with`",
+                        ));
+                    }
+                    let module: syn::Path = meta.value()?.parse()?;
+                    result.encode_with = Some(with_fn(&module, "encode"));
+                    result.decode_with = Some(with_fn(&module, "decode"));
+                } else if meta.path.is_ident("encode_with") {
+                    set_once(&meta, &mut result.encode_with, meta.value()?.parse()?)?;
+                } else if meta.path.is_ident("decode_with") {
+                    set_once(&meta, &mut result.decode_with, meta.value()?.parse()?)?;
+                } else if meta.path.is_ident("rename") {
+                    set_once(&meta, &mut result.rename, parse_name(&meta)?)?;
+                } else if meta.path.is_ident("boxed") {
+                    result.boxed = true;
+                } else {
+                    return Err(meta.error("unsupported cerdito field attribute"));
+                }
+                if result.required && result.default.is_some() {
+                    return Err(meta.error("`default` and `required` can't be used together"));
+                }
+                if result.required && result.skip_decoding {
+                    return Err(meta.error("skipped field can't be `required`"));
+                }
+                if result.skip_encoding && result.encode_with.is_some()
+                    || result.skip_decoding && result.decode_with.is_some()
+                {
+                    return Err(meta.error("skipped field can't have a custom codec"));
+                }
+                if result.boxed && (result.encode_with.is_some() || result.decode_with.is_some()) {
+                    return Err(meta.error("`boxed` field can't have a custom codec"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(result)
+    }
+}
+
+/// Stores the value of an attribute that may be given only once.
+fn set_once<T>(
+    meta: &syn::meta::ParseNestedMeta,
+    slot: &mut Option<T>,
+    value: T,
+) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(meta.error(format!(
+            "duplicate `{}` attribute",
+            meta.path.to_token_stream()
+        )));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+fn parse_name(meta: &syn::meta::ParseNestedMeta) -> syn::Result<String> {
+    Ok(meta.value()?.parse::<syn::LitStr>()?.value())
+}
+
+/// Parses a string with comma separated where predicates.
+fn parse_bound(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Vec<syn::WherePredicate>> {
+    let lit: syn::LitStr = meta.value()?.parse()?;
+    let predicates = lit.parse_with(
+        syn::punctuated::Punctuated::<syn::WherePredicate, syn::Token![,]>::parse_terminated,
+    )?;
+    Ok(predicates.into_iter().collect())
+}
+
+fn parse_rule(meta: &syn::meta::ParseNestedMeta) -> syn::Result<RenameRule> {
+    RenameRule::parse(&meta.value()?.parse()?)
+}
+
+/// Path of the `encode` or `decode` function of a `with` module.
+fn with_fn(module: &syn::Path, name: &str) -> syn::Path {
+    let mut path = module.clone();
+    path.segments
+        .push(syn::Ident::new(name, proc_macro2::Span::call_site()).into());
+    path
+}
+
+/// Case convention of `rename_all`.
+#[derive(Clone, Copy)]
+pub enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+const RENAME_RULES: [(&str, RenameRule); 8] = [
+    ("lowercase", RenameRule::Lower),
+    ("UPPERCASE", RenameRule::Upper),
+    ("PascalCase", RenameRule::Pascal),
+    ("camelCase", RenameRule::Camel),
+    ("snake_case", RenameRule::Snake),
+    ("SCREAMING_SNAKE_CASE", RenameRule::ScreamingSnake),
+    ("kebab-case", RenameRule::Kebab),
+    ("SCREAMING-KEBAB-CASE", RenameRule::ScreamingKebab),
+];
+
+impl RenameRule {
+    fn parse(lit: &syn::LitStr) -> syn::Result<Self> {
+        let value = lit.value();
+        RENAME_RULES
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, rule)| *rule)
+            .ok_or_else(|| {
+                let names: Vec<_> = RENAME_RULES.iter().map(|(name, _)| *name).collect();
+                syn::Error::new(
+                    lit.span(),
+                    format!("unknown rename rule, expected one of {}", names.join(", ")),
+                )
+            })
+    }
+
+    /// Converts a `PascalCase` variant or `snake_case` field name.
+    pub fn apply(self, name: &str) -> String {
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |c| {
+                c.to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect()
+            })
+        };
+        let words = words(name);
+        match self {
+            Self::Lower => name.to_lowercase(),
+            Self::Upper => name.to_uppercase(),
+            Self::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+            Self::Camel => words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    if i == 0 {
+                        w.to_lowercase()
+                    } else {
+                        capitalize(w)
+                    }
+                })
+                .collect(),
+            Self::Snake => words.join("_").to_lowercase(),
+            Self::ScreamingSnake => words.join("_").to_uppercase(),
+            Self::Kebab => words.join("-").to_lowercase(),
+            Self::ScreamingKebab => words.join("-").to_uppercase(),
+        }
+    }
+}
+
+/// Splits a name at underscores and at case boundaries, `HTTPServer2Id` gives `HTTP`,
+/// `Server2`, `Id`.
+fn words(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+        for (j, &(i, c)) in chars.iter().enumerate().skip(1) {
+            let prev = chars[j - 1].1;
+            let next_lower = chars.get(j + 1).is_some_and(|&(_, c)| c.is_lowercase());
+            if c.is_uppercase() && (!prev.is_uppercase() || next_lower) {
+                words.push(&part[start..i]);
+                start = i;
+            }
+        }
+        words.push(&part[start..]);
+    }
+    words
+}
+
+/// Name of an item passed to the hooks: `rename`, or the identifier converted with
+/// `rename_all`.
+pub fn rename(ident: &syn::Ident, rename: Option<&str>, rename_all: Option<RenameRule>) -> String {
+    use syn::ext::IdentExt;
+    let name = ident.unraw().to_string();
+    match (rename, rename_all) {
+        (Some(rename), _) => rename.to_owned(),
+        (None, Some(rule)) => rule.apply(&name),
+        (None, None) => name,
+    }
+}
@@ -0,0 +1,150 @@
+//! Trait bounds of the generated impls.
+
+use crate::{attr, get_fields, needs_default, Field};
+use quote::{quote, ToTokens};
+
+/// Trait implemented by the derive.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Derive {
+    Encode,
+    Decode,
+}
+
+/// Generics of the impl: the type's generics plus `#[cerdito(bound = "...")]` or the inferred
+/// bounds, those of `SendEncode` or `SendDecode` when `send`.
+pub fn impl_generics(
+    ast: &syn::DeriveInput,
+    attrs: &attr::ContainerAttrs,
+    derive: Derive,
+    send: bool,
+) -> syn::Result<syn::Generics> {
+    let bound = match derive {
+        Derive::Encode => &attrs.encode_bound,
+        Derive::Decode => &attrs.decode_bound,
+    };
+    let predicates = match bound {
+        Some(predicates) => predicates.clone(),
+        None => infer_bounds(ast, attrs, derive, send)?,
+    };
+    let mut generics = ast.generics.clone();
+    generics.make_where_clause().predicates.extend(predicates);
+    Ok(generics)
+}
+
+/// `FieldType: Encode` or `FieldType: Decode` for encoded or decoded fields that use a type
+/// parameter, `FieldType: Default` for such fields that may be filled by default. When `send`,
+/// `SendEncode` or `SendDecode` instead, and `FieldType: Sync` or `FieldType: Send` for the
+/// other fields that use a type parameter.
+fn infer_bounds(
+    ast: &syn::DeriveInput,
+    attrs: &attr::ContainerAttrs,
+    derive: Derive,
+    send: bool,
+) -> syn::Result<Vec<syn::WherePredicate>> {
+    let params: Vec<&syn::Ident> = ast.generics.type_params().map(|p| &p.ident).collect();
+    if params.is_empty() {
+        return Ok(Vec::new());
+    }
+    let fields: Vec<Field> = match &ast.data {
+        syn::Data::Struct(data) => get_fields(&data.fields, &ast.ident, None)?,
+        syn::Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|v| get_fields(&v.fields, &ast.ident, None))
+            .collect::<syn::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+
+    let codec = match (derive, send) {
+        (Derive::Encode, false) => quote!(__cerdito::Encode),
+        (Derive::Decode, false) => quote!(__cerdito::Decode),
+        (Derive::Encode, true) => quote!(__cerdito::SendEncode),
+        (Derive::Decode, true) => quote!(__cerdito::SendDecode),
+    };
+    let marker = match derive {
+        Derive::Encode => quote!(::core::marker::Sync),
+        Derive::Decode => quote!(::core::marker::Send),
+    };
+    let mut predicates: Vec<proc_macro2::TokenStream> = Vec::new();
+    let mut push = |predicate: proc_macro2::TokenStream| {
+        if !predicates
+            .iter()
+            .any(|p| p.to_string() == predicate.to_string())
+        {
+            predicates.push(predicate);
+        }
+    };
+    for f in &fields {
+        let (skipped, custom) = match derive {
+            Derive::Encode => (f.attrs.skip_encoding, f.attrs.encode_with.is_some()),
+            Derive::Decode => (f.attrs.skip_decoding, f.attrs.decode_with.is_some()),
+        };
+        let ty = &f.ty;
+        let mentioned = |ident: &syn::Ident| mentions(ty.to_token_stream(), ident);
+        if !params.iter().any(|p| mentioned(p)) {
+            continue;
+        }
+        // a recursive field is coded by the impl being defined, bounding it would be a cycle
+        if !skipped && !custom && !refers_to_self(ty, &ast.ident) {
+            push(quote!(#ty: #codec));
+        } else if send {
+            // the `Send` futures hold all fields, even skipped or recursive ones
+            push(quote!(#ty: #marker));
+        }
+        // the only field of a transparent struct is never missing
+        let defaulted =
+            derive == Derive::Decode && (skipped || !attrs.transparent) && needs_default(f);
+        if defaulted {
+            let ty = default_type(ty);
+            push(quote!(#ty: ::std::default::Default));
+        }
+    }
+    predicates.into_iter().map(syn::parse2).collect()
+}
+
+/// Type that must implement `Default` to fill a missing field, see `default_value`.
+pub fn default_type(ty: &syn::Type) -> &syn::Type {
+    match ty {
+        syn::Type::Array(array) => default_type(&array.elem),
+        syn::Type::Paren(paren) => default_type(&paren.elem),
+        syn::Type::Group(group) => default_type(&group.elem),
+        _ => ty,
+    }
+}
+
+/// Whether the tokens contain the identifier, e.g. a type parameter in a field type.
+pub fn mentions(tokens: proc_macro2::TokenStream, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(i) => i == *ident,
+        proc_macro2::TokenTree::Group(group) => mentions(group.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Whether the type refers to the type being derived, by `Self` or by its name without a
+/// path, e.g. `Vec<Tree>` but not `other::Tree`.
+pub fn refers_to_self(ty: &syn::Type, container: &syn::Ident) -> bool {
+    struct Visitor<'a> {
+        container: &'a syn::Ident,
+        found: bool,
+    }
+    impl<'ast> syn::visit::Visit<'ast> for Visitor<'_> {
+        fn visit_type_path(&mut self, ty: &'ast syn::TypePath) {
+            let path = &ty.path;
+            if ty.qself.is_none() && path.leading_colon.is_none() && path.segments.len() == 1 {
+                let ident = &path.segments[0].ident;
+                self.found |= ident == "Self" || ident == self.container;
+            }
+            syn::visit::visit_type_path(self, ty);
+        }
+    }
+    let mut visitor = Visitor {
+        container,
+        found: false,
+    };
+    syn::visit::Visit::visit_type(&mut visitor, ty);
+    visitor.found
+}
@@ -1,127 +1,478 @@
 extern crate proc_macro;
-use quote::quote;
+use quote::{quote, ToTokens};
+use syn::spanned::Spanned;
 
-#[proc_macro_derive(Encode)]
+mod attr;
+mod bound;
+mod send;
+
+#[proc_macro_derive(Encode, attributes(cerdito))]
 pub fn encode_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    match expand_encode(&ast, false) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(Decode, attributes(cerdito))]
+pub fn decode_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    match expand_decode(&ast, false) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// `SendEncode` next to a derived `Encode`, with the same attributes.
+#[proc_macro_derive(SendEncode, attributes(cerdito))]
+pub fn send_encode_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    match expand_encode(&ast, true) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// `SendDecode` next to a derived `Decode`, with the same attributes.
+#[proc_macro_derive(SendDecode, attributes(cerdito))]
+pub fn send_decode_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    match expand_decode(&ast, true) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Adds the `Send` variant of a trait, impl or function written with `#[_async]`.
+///
+/// * On a trait `X`, adds `trait SendX: X` with a `*_send` method returning
+///   `impl Future + Send` for every async method, e.g. `SendDecoder`.
+/// * On an impl of `X`, adds the impl of `SendX` with `async fn *_send` methods, and on an
+///   inherent impl, the `*_send` functions next to the `*_async` ones.
+/// * On a function `f`, adds `async fn f_send`, e.g. for `#[cerdito(with = module)]`.
+///
+/// In the bounds of the variant `Decoder`, `Encode`, ... become `SendDecoder`, `SendEncode`,
+/// ..., and the arguments are added to its where clause, e.g. `#[send_variant(T: Send)]`. In
+/// its body `_await!(x.f(..))` becomes `_await_send!(x.f(..))`, so `_await_send` must be in
+/// scope like `build_async::*` is, and calls of the hand-written `async fn *_async` of the
+/// same trait or impl are renamed to `*_send`.
+#[proc_macro_attribute]
+pub fn send_variant(
+    attr: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    match send::send_variant(attr.into(), input.into()) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// `_await!` in the `Send` variants generated by `send_variant`: `_await_send!(x.f(args))`
+/// is `x.f_send(args).await`, and `_await_send!(<T as Decode>::f(args))` is
+/// `<T as SendDecode>::f_send(args).await`.
+#[proc_macro]
+pub fn _await_send(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    match send::await_send(input.into()) {
+        Ok(expanded) => proc_macro::TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// `Encode` impl, or `SendEncode` impl when `send`.
+fn expand_encode(ast: &syn::DeriveInput, send: bool) -> syn::Result<proc_macro2::TokenStream> {
     let name = &ast.ident;
-    let (impl_generics, type_generics, where_clause) = ast.generics.split_for_impl();
+    let attrs = attr::ContainerAttrs::parse(&ast.attrs)?;
     let body = match ast.data {
-        syn::Data::Struct(ref data) => generate_encode_for_struct(data, &name),
-        syn::Data::Enum(ref data) => generate_encode_for_enum(data, &name),
-        syn::Data::Union(_) => unimplemented!("Unions are not supported"),
-    };
-    let expanded = quote! {
-        #[automatically_derived]
-        impl #impl_generics ::cerdito::Encode for #name #type_generics #where_clause {
-            #[_async] fn encode<__CerditoEncoderTypeParam: ::cerdito::Encoder>(
-                &self,
-                encoder: &mut __CerditoEncoderTypeParam
-            ) -> Result<(), __CerditoEncoderTypeParam::Error> {
-                #body
+        syn::Data::Struct(ref data) if attrs.transparent => {
+            generate_encode_for_transparent(data, name)
+        }
+        syn::Data::Enum(_) if attrs.transparent => Err(syn::Error::new(
+            name.span(),
+            "`transparent` is only supported for structs",
+        )),
+        syn::Data::Struct(ref data) => generate_encode_for_struct(data, name, &attrs),
+        syn::Data::Enum(ref data) if data.variants.is_empty() => Err(syn::Error::new_spanned(
+            name,
+            "enums without variants are not supported",
+        )),
+        syn::Data::Enum(ref data) => generate_encode_for_enum(data, name, &attrs),
+        syn::Data::Union(ref data) => Err(syn::Error::new(
+            data.union_token.span,
+            "unions are not supported",
+        )),
+    }?;
+    let tag_consts = tag_consts(&ast.data, &attrs)?;
+    let generics = bound::impl_generics(ast, &attrs, bound::Derive::Encode, send)?;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let item = match send {
+        false => quote! {
+            #[automatically_derived]
+            impl #impl_generics __cerdito::Encode for #name #type_generics #where_clause {
+                #[_async] fn encode<__CerditoEncoderTypeParam: __cerdito::Encoder>(
+                    &self,
+                    encoder: &mut __CerditoEncoderTypeParam
+                ) -> Result<(), __CerditoEncoderTypeParam::Error> {
+                    #body
+                }
+            }
+        },
+        true => {
+            let body = send::send_body(body);
+            quote! {
+                #[allow(unused_imports)]
+                use __cerdito::SendEncode as _;
+                #[automatically_derived]
+                impl #impl_generics __cerdito::SendEncode for #name #type_generics #where_clause {
+                    async fn encode_send<__CerditoEncoderTypeParam: __cerdito::SendEncoder>(
+                        &self,
+                        encoder: &mut __CerditoEncoderTypeParam
+                    ) -> Result<(), __CerditoEncoderTypeParam::Error> {
+                        #body
+                    }
+                }
             }
         }
     };
-    proc_macro::TokenStream::from(expanded)
+    Ok(in_scope(&attrs, quote! { #tag_consts #item }))
 }
 
-#[proc_macro_derive(Decode)]
-pub fn decode_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+/// `Decode` impl, or `SendDecode` impl when `send`.
+fn expand_decode(ast: &syn::DeriveInput, send: bool) -> syn::Result<proc_macro2::TokenStream> {
     let name = &ast.ident;
-    let (impl_generics, type_generics, where_clause) = ast.generics.split_for_impl();
+    let attrs = attr::ContainerAttrs::parse(&ast.attrs)?;
     let body = match ast.data {
-        syn::Data::Struct(ref data) => generate_decode_for_struct(data, &name),
-        syn::Data::Enum(ref data) => generate_decode_for_enum(data, &name),
-        syn::Data::Union(_) => unimplemented!("Unions are not supported"),
-    };
-    let expanded = quote! {
-        #[automatically_derived]
-        impl #impl_generics ::cerdito::Decode for #name #type_generics #where_clause {
-            #[_async] fn decode<__CerditoDecoderTypeParam: ::cerdito::Decoder>(
-                decoder: &mut __CerditoDecoderTypeParam
-            ) -> Result<Self, __CerditoDecoderTypeParam::Error> {
-                #body
+        syn::Data::Struct(ref data) if attrs.transparent => {
+            generate_decode_for_transparent(data, name)
+        }
+        syn::Data::Enum(_) if attrs.transparent => Err(syn::Error::new(
+            name.span(),
+            "`transparent` is only supported for structs",
+        )),
+        syn::Data::Struct(ref data) => generate_decode_for_struct(data, name, &attrs),
+        syn::Data::Enum(ref data) if data.variants.is_empty() => Err(syn::Error::new_spanned(
+            name,
+            "enums without variants are not supported",
+        )),
+        syn::Data::Enum(ref data) => generate_decode_for_enum(data, name, &attrs),
+        syn::Data::Union(ref data) => Err(syn::Error::new(
+            data.union_token.span,
+            "unions are not supported",
+        )),
+    }?;
+    let tag_consts = tag_consts(&ast.data, &attrs)?;
+    let generics = bound::impl_generics(ast, &attrs, bound::Derive::Decode, send)?;
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let item = match send {
+        false => quote! {
+            #[automatically_derived]
+            impl #impl_generics __cerdito::Decode for #name #type_generics #where_clause {
+                #[_async] fn decode<__CerditoDecoderTypeParam: __cerdito::Decoder>(
+                    decoder: &mut __CerditoDecoderTypeParam
+                ) -> Result<Self, __CerditoDecoderTypeParam::Error> {
+                    #body
+                }
+            }
+        },
+        true => {
+            let body = send::send_body(body);
+            quote! {
+                #[automatically_derived]
+                impl #impl_generics __cerdito::SendDecode for #name #type_generics #where_clause {
+                    async fn decode_send<__CerditoDecoderTypeParam: __cerdito::SendDecoder>(
+                        decoder: &mut __CerditoDecoderTypeParam
+                    ) -> Result<Self, __CerditoDecoderTypeParam::Error> {
+                        #body
+                    }
+                }
             }
         }
     };
-    proc_macro::TokenStream::from(expanded)
+    Ok(in_scope(&attrs, quote! { #tag_consts #item }))
 }
 
-fn get_fields(fields: &syn::Fields) -> Vec<(usize, proc_macro2::Ident, String, syn::Type)> {
-    match fields {
-        syn::Fields::Named(fields) => fields
-            .named
-            .iter()
-            .enumerate()
-            .map(|(i, f)| {
-                let default_name = format!("field_{}", i);
-                let field_ident = f
-                    .ident
-                    .clone()
-                    .or(Some(proc_macro2::Ident::new(
-                        &default_name,
-                        proc_macro2::Span::call_site(),
-                    )))
-                    .unwrap();
-                let field_name = field_ident.to_string();
-                (i, field_ident, field_name, f.ty.clone())
+/// Consts computing the enum tags that aren't integer literals, next to the impl that uses
+/// them.
+fn tag_consts(
+    data: &syn::Data,
+    attrs: &attr::ContainerAttrs,
+) -> syn::Result<proc_macro2::TokenStream> {
+    match data {
+        syn::Data::Enum(data) if !attrs.transparent => Ok(generate_tags(data, attrs)?.consts),
+        _ => Ok(proc_macro2::TokenStream::new()),
+    }
+}
+
+/// Puts the impl in a scope where `__cerdito` is the `cerdito` crate and the `build_async`
+/// macros are visible, so that users don't have to import them.
+fn in_scope(
+    attrs: &attr::ContainerAttrs,
+    item: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let krate = attrs.krate();
+    quote! {
+        const _: () = {
+            use #krate as __cerdito;
+            #[allow(unused_imports)]
+            use __cerdito::__private::{_async, _await_async, _await_send, _await_sync};
+            #item
+        };
+    }
+}
+
+struct Field {
+    ident: proc_macro2::Ident,
+    /// `Some(name)` or `None` expression passed to the elem hooks.
+    name: proc_macro2::TokenStream,
+    /// Name reported in missing field errors, the index for tuple fields.
+    error_name: String,
+    ty: syn::Type,
+    attrs: attr::FieldAttrs,
+    /// Whether the async futures are boxed: `#[cerdito(boxed)]` or the field type refers to the
+    /// type being derived, since a recursive future would have infinite size.
+    boxed: bool,
+}
+
+/// Fields of a struct or variant of type `container`, `rename_all` is the rule of the struct
+/// or variant.
+fn get_fields(
+    fields: &syn::Fields,
+    container: &syn::Ident,
+    rename_all: Option<attr::RenameRule>,
+) -> syn::Result<Vec<Field>> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, f)| {
+            let attrs = attr::FieldAttrs::parse(&f.attrs)?;
+            let ident = match &f.ident {
+                Some(ident) => ident.clone(),
+                None => proc_macro2::Ident::new(
+                    &format!("field_{}", index),
+                    proc_macro2::Span::call_site(),
+                ),
+            };
+            let (name, error_name) = match (&f.ident, &attrs.rename) {
+                (Some(ident), rename) => {
+                    let field_name = attr::rename(ident, rename.as_deref(), rename_all);
+                    (quote! { Some(#field_name) }, field_name)
+                }
+                (None, Some(field_name)) => (quote! { Some(#field_name) }, field_name.clone()),
+                (None, None) => (quote! { None }, index.to_string()),
+            };
+            let boxed = attrs.boxed || bound::refers_to_self(&f.ty, container);
+            Ok(Field {
+                ident,
+                name,
+                error_name,
+                ty: f.ty.clone(),
+                attrs,
+                boxed,
             })
-            .collect(),
-        syn::Fields::Unnamed(fields) => fields
-            .unnamed
+        })
+        .collect()
+}
+
+/// Value of a field that is missing in old data: the `default` attribute, an error for
+/// `required` fields, or `Default` of the field type otherwise.
+fn missing_value(field: &Field) -> proc_macro2::TokenStream {
+    let error_name = &field.error_name;
+    let missing_field = quote! {
+        return Err(decoder.annotate(<__CerditoDecoderTypeParam::Error as __cerdito::Error>::missing_field(#error_name)))
+    };
+    if let Some(expr) = &field.attrs.default {
+        quote! { #expr }
+    } else if fails_when_missing(field) {
+        missing_field
+    } else if !field.attrs.skip_decoding && is_array(&field.ty) {
+        // `Default` of the elements if they implement it, without requiring it
+        let elem = bound::default_type(&field.ty);
+        let value = default_value_with(&field.ty, &quote! { __cerdito_elem() });
+        quote! {
+            match {
+                use __cerdito::__private::{HasElemDefault, NoElemDefault};
+                (&__cerdito::__private::ElemDefault::<#elem>(::std::marker::PhantomData)).elem_default()
+            } {
+                Some(__cerdito_elem) => #value,
+                None => #missing_field,
+            }
+        }
+    } else {
+        default_value(&field.ty)
+    }
+}
+
+/// Whether decoding fails with a missing-field error when the field isn't in the input.
+fn fails_when_missing(field: &Field) -> bool {
+    field.attrs.default.is_none() && field.attrs.required
+}
+
+/// Whether the field type must implement `Default` to fill the field when it is missing.
+/// A decoded array field is filled only if its elements implement `Default`, so that
+/// `[T; N]` needs neither `T: Default` nor `N <= 32`.
+fn needs_default(field: &Field) -> bool {
+    field.attrs.default.is_none()
+        && !field.attrs.required
+        && (field.attrs.skip_decoding || !is_array(&field.ty))
+}
+
+fn is_array(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Array(_) => true,
+        syn::Type::Paren(paren) => is_array(&paren.elem),
+        syn::Type::Group(group) => is_array(&group.elem),
+        _ => false,
+    }
+}
+
+/// Default value of a skipped field. Arrays are built element by element, since `[T; N]`
+/// implements `Default` only for `N <= 32`.
+fn default_value(ty: &syn::Type) -> proc_macro2::TokenStream {
+    // spanned so that a missing `Default` impl is reported at the field
+    let elem = bound::default_type(ty);
+    default_value_with(
+        ty,
+        &quote::quote_spanned! {ty.span()=> <#elem as ::std::default::Default>::default() },
+    )
+}
+
+/// Value of the type with all array elements set to `elem`.
+fn default_value_with(ty: &syn::Type, elem: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match ty {
+        syn::Type::Array(array) => {
+            let elem = default_value_with(&array.elem, elem);
+            quote! { ::std::array::from_fn(|_| #elem) }
+        }
+        syn::Type::Paren(paren) => default_value_with(&paren.elem, elem),
+        syn::Type::Group(group) => default_value_with(&group.elem, elem),
+        _ => elem.clone(),
+    }
+}
+
+fn field_idents(fields: &[Field]) -> Vec<&proc_macro2::Ident> {
+    fields.iter().map(|f| &f.ident).collect()
+}
+
+/// Bindings of the fields that are encoded, for a `Self { .. }` or `Self(..)` pattern.
+fn encode_bindings(fields: &[Field], named: bool) -> Vec<proc_macro2::TokenStream> {
+    if named {
+        fields
+            .iter()
+            .filter(|f| !f.attrs.skip_encoding)
+            .map(|f| f.ident.to_token_stream())
+            .chain(std::iter::once(quote! { .. }))
+            .collect()
+    } else {
+        fields
             .iter()
-            .enumerate()
-            .map(|(i, f)| {
-                let default_name = format!("field_{}", i);
-                let field_ident = f
-                    .ident
-                    .clone()
-                    .or(Some(proc_macro2::Ident::new(
-                        &default_name,
-                        proc_macro2::Span::call_site(),
-                    )))
-                    .unwrap();
-                let field_name = field_ident.to_string();
-                (i, field_ident, field_name, f.ty.clone())
+            .map(|f| match f.attrs.skip_encoding {
+                true => quote! { _ },
+                false => f.ident.to_token_stream(),
             })
-            .collect(),
-        syn::Fields::Unit => vec![],
+            .collect()
     }
 }
 
-fn generate_encode_for_struct(
-    data: &syn::DataStruct,
-    name: &proc_macro2::Ident,
-) -> proc_macro2::TokenStream {
-    let name_str = name.to_string();
-    let fields = get_fields(&data.fields);
-    let field_idents: Vec<_> = fields
-        .iter()
-        .map(|(_, ident, _, _)| ident.clone())
-        .collect();
-    let field_codes: Vec<_> = fields
+/// Number of elements on the wire, i.e. fields that are not skipped.
+fn encoded_len(fields: &[Field]) -> usize {
+    fields.iter().filter(|f| !f.attrs.skip_encoding).count()
+}
+
+fn decoded_len(fields: &[Field]) -> usize {
+    fields.iter().filter(|f| !f.attrs.skip_decoding).count()
+}
+
+/// Encoding of a field bound to its identifier, without `?`.
+fn encode_value(field: &Field) -> proc_macro2::TokenStream {
+    let field_ident = &field.ident;
+    match &field.attrs.encode_with {
+        Some(path) => quote! { _await!(#path(#field_ident, encoder)) },
+        None if field.boxed => {
+            quote! { _await!(__cerdito::__private::encode_boxed(#field_ident, encoder)) }
+        }
+        None => quote! { _await!(#field_ident.encode(encoder)) },
+    }
+}
+
+/// Decoding of a field, without `?`.
+fn decode_value(field: &Field) -> proc_macro2::TokenStream {
+    let field_type = &field.ty;
+    match &field.attrs.decode_with {
+        Some(path) => quote! { _await!(#path(decoder)) },
+        None if field.boxed => {
+            quote! { _await!(__cerdito::__private::decode_boxed::<#field_type, _>(decoder)) }
+        }
+        None => quote! { _await!(<#field_type as __cerdito::Decode>::decode(decoder)) },
+    }
+}
+
+fn encode_field_codes(fields: &[Field]) -> Vec<proc_macro2::TokenStream> {
+    fields
         .iter()
-        .map(|(i, field_ident, field_name, _)| {
+        .filter(|f| !f.attrs.skip_encoding)
+        .enumerate()
+        .map(|(i, f)| {
+            let field_name = &f.name;
+            let encode = encode_value(f);
             quote! {
-                _await!(encoder.encode_elem_begin(#i, Some(#field_name)))?;
-                _await!(#field_ident.encode(encoder))?;
+                _await!(encoder.encode_elem_begin(#i, #field_name))?;
+                #encode?;
                 _await!(encoder.encode_elem_end())?;
             }
         })
-        .collect();
-    let fields_len = fields.len();
-    match &data.fields {
+        .collect()
+}
+
+fn decode_field_codes(fields: &[Field]) -> Vec<proc_macro2::TokenStream> {
+    let mut i = 0_usize;
+    fields
+        .iter()
+        .map(|f| {
+            let (field_ident, field_name, field_type) = (&f.ident, &f.name, &f.ty);
+            let missing = missing_value(f);
+            if f.attrs.skip_decoding {
+                return quote! {
+                    let #field_ident = #missing;
+                };
+            }
+            let decode = decode_value(f);
+            let code = quote! {
+                _await!(decoder.decode_elem_begin(#i, #field_name))?;
+                let #field_ident: #field_type = if #i < __cerdito_len {
+                    #decode?
+                } else { // new program, old data
+                    #missing
+                };
+                _await!(decoder.decode_elem_end())?;
+            };
+            i += 1;
+            code
+        })
+        .collect()
+}
+
+fn generate_encode_for_struct(
+    data: &syn::DataStruct,
+    name: &proc_macro2::Ident,
+    attrs: &attr::ContainerAttrs,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name_str = attrs.name(name);
+    let fields = get_fields(&data.fields, name, attrs.rename_all)?;
+    let named_bindings = encode_bindings(&fields, true);
+    let unnamed_bindings = encode_bindings(&fields, false);
+    let field_codes = encode_field_codes(&fields);
+    let fields_len = encoded_len(&fields);
+    Ok(match &data.fields {
         syn::Fields::Named(_) => quote! {
             _await!(encoder.encode_struct_begin(#fields_len, Some(#name_str)))?;
-            let Self { #(#field_idents),* } = self;
+            let Self { #(#named_bindings),* } = self;
             #(#field_codes)*
             _await!(encoder.encode_struct_end())?;
             Ok(())
         },
         syn::Fields::Unnamed(_) => quote! {
             _await!(encoder.encode_struct_begin(#fields_len, Some(#name_str)))?;
-            let Self( #(#field_idents),* ) = self;
+            let Self( #(#unnamed_bindings),* ) = self;
             #(#field_codes)*
             _await!(encoder.encode_struct_end())?;
             Ok(())
@@ -131,34 +482,19 @@ fn generate_encode_for_struct(
             _await!(encoder.encode_struct_end())?;
             Ok(())
         },
-    }
+    })
 }
 
 fn generate_decode_for_struct(
     data: &syn::DataStruct,
     name: &proc_macro2::Ident,
-) -> proc_macro2::TokenStream {
-    let name_str = name.to_string();
-    let fields = get_fields(&data.fields);
-    let field_idents: Vec<_> = fields
-        .iter()
-        .map(|(_, ident, _, _)| ident.clone())
-        .collect();
-    let field_codes: Vec<_> = fields
-        .iter()
-        .map(|(i, field_ident, field_name, field_type)| {
-            quote! {
-                _await!(decoder.decode_elem_begin(#i, Some(#field_name)))?;
-                let #field_ident = if #i < __cerdito_len {
-                    _await!(<#field_type as ::cerdito::Decode>::decode(decoder))?
-                } else { // new program, old data
-                    <#field_type>::default() // TODO: Or fail if Default isn't implemented?
-                };
-                _await!(decoder.decode_elem_end())?;
-            }
-        })
-        .collect();
-    let fields_len = fields.len();
+    attrs: &attr::ContainerAttrs,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name_str = attrs.name(name);
+    let fields = get_fields(&data.fields, name, attrs.rename_all)?;
+    let field_idents = field_idents(&fields);
+    let field_codes = decode_field_codes(&fields);
+    let fields_len = decoded_len(&fields);
 
     let compat = quote! {
         // old program, new data
@@ -167,7 +503,7 @@ fn generate_decode_for_struct(
         }
     };
 
-    match &data.fields {
+    Ok(match &data.fields {
         syn::Fields::Named(_) => quote! {
             let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, Some(#name_str)))?;
             #(#field_codes)*
@@ -188,144 +524,409 @@ fn generate_decode_for_struct(
             _await!(decoder.decode_struct_end())?;
             Ok(Self)
         },
+    })
+}
+
+/// The only field of a transparent struct that is encoded and decoded, other fields must be
+/// skipped.
+fn transparent_field<'a>(fields: &'a [Field], name: &syn::Ident) -> syn::Result<&'a Field> {
+    let mut inner = fields
+        .iter()
+        .filter(|f| !f.attrs.skip_encoding || !f.attrs.skip_decoding);
+    match (inner.next(), inner.next()) {
+        (Some(field), None) if !field.attrs.skip_encoding && !field.attrs.skip_decoding => {
+            Ok(field)
+        }
+        _ => Err(syn::Error::new(
+            name.span(),
+            "`transparent` struct must have exactly one field that is not skipped",
+        )),
     }
 }
 
-fn generate_tags(data: &syn::DataEnum) -> Vec<proc_macro2::TokenStream> {
-    let mut current_expr: Option<proc_macro2::TokenStream> = None;
-    let mut current_incr: u32 = 0;
-    data.variants
+fn generate_encode_for_transparent(
+    data: &syn::DataStruct,
+    name: &proc_macro2::Ident,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = get_fields(&data.fields, name, None)?;
+    let field = transparent_field(&fields, name)?;
+    let bindings = encode_bindings(&fields, matches!(data.fields, syn::Fields::Named(_)));
+    let encode = encode_value(field);
+    Ok(match &data.fields {
+        syn::Fields::Named(_) => quote! {
+            let Self { #(#bindings),* } = self;
+            #encode
+        },
+        _ => quote! {
+            let Self( #(#bindings),* ) = self;
+            #encode
+        },
+    })
+}
+
+fn generate_decode_for_transparent(
+    data: &syn::DataStruct,
+    name: &proc_macro2::Ident,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = get_fields(&data.fields, name, None)?;
+    let field = transparent_field(&fields, name)?;
+    let field_idents = field_idents(&fields);
+    let field_codes: Vec<_> = fields
         .iter()
-        .map(|v| match &v.discriminant {
-            Some((_, expr)) => {
-                let e = quote! { #expr };
-                current_expr = Some(e.clone());
-                current_incr = 1;
-                e
+        .map(|f| {
+            let (field_ident, field_type) = (&f.ident, &f.ty);
+            if !std::ptr::eq(f, field) {
+                let missing = missing_value(f);
+                return quote! { let #field_ident = #missing; };
             }
-            None => match &current_expr {
-                Some(expr) => {
-                    let e = quote! { (#expr) + #current_incr };
-                    current_incr += 1;
-                    e
-                }
-                None => {
-                    let e = quote! { #current_incr };
-                    current_incr += 1;
-                    e
-                }
-            },
+            let decode = decode_value(f);
+            quote! { let #field_ident: #field_type = #decode?; }
         })
-        .collect()
+        .collect();
+    Ok(match &data.fields {
+        syn::Fields::Named(_) => quote! {
+            #(#field_codes)*
+            Ok(Self { #(#field_idents),* })
+        },
+        _ => quote! {
+            #(#field_codes)*
+            Ok(Self( #(#field_idents),* ))
+        },
+    })
+}
+
+/// Wire tag of an enum variant, known to the derive or computed by a const.
+enum Tag {
+    Lit(u32),
+    Const(syn::Ident),
+}
+
+impl ToTokens for Tag {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        match self {
+            Tag::Lit(value) => value.to_tokens(tokens),
+            Tag::Const(ident) => ident.to_tokens(tokens),
+        }
+    }
+}
+
+/// Wire tags of an enum variant.
+struct Tags {
+    tag: Tag,
+    /// Legacy tags accepted on decode.
+    aliases: Vec<Tag>,
+}
+
+/// Tags of all variants and the consts that compute and check them.
+struct EnumTags {
+    tags: Vec<Tags>,
+    consts: proc_macro2::TokenStream,
+}
+
+const TAG_RANGE_ERROR: &str = "enum tag is out of range 0..=4294967295";
+
+/// Checks that an enum tag fits in `u32`.
+fn tag_value(value: i128, span: proc_macro2::Span) -> syn::Result<u32> {
+    u32::try_from(value).map_err(|_| {
+        syn::Error::new(
+            span,
+            format!("enum tag {} is out of range 0..=4294967295", value),
+        )
+    })
+}
+
+/// Value of a tag given by an integer literal, `None` for other expressions.
+fn lit_tag(expr: &syn::Expr) -> Option<syn::Result<u32>> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(lit),
+            ..
+        }) => Some(
+            lit.base10_parse()
+                .and_then(|value| tag_value(value, lit.span())),
+        ),
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_),
+            expr,
+            ..
+        }) => match &**expr {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(lit),
+                ..
+            }) => Some(
+                lit.base10_parse::<i128>()
+                    .and_then(|value| tag_value(-value, lit.span())),
+            ),
+            _ => None,
+        },
+        syn::Expr::Paren(syn::ExprParen { expr, .. })
+        | syn::Expr::Group(syn::ExprGroup { expr, .. }) => lit_tag(expr),
+        _ => None,
+    }
+}
+
+/// Tag given by an expression: literals are checked here, other expressions are evaluated by
+/// a const that fails to compile if the value is out of range. `ty` is the type of the
+/// expression if it is a discriminant.
+fn expr_tag(
+    expr: &syn::Expr,
+    ty: Option<&proc_macro2::TokenStream>,
+    ident: syn::Ident,
+    consts: &mut proc_macro2::TokenStream,
+) -> syn::Result<Tag> {
+    if let Some(value) = lit_tag(expr) {
+        return value.map(Tag::Lit);
+    }
+    let ty = ty.map(|ty| quote!(: #ty));
+    // only the assert points at the expression, the casts are not user code
+    let in_range = quote!(value as i128 >= 0 && value as i128 <= u32::MAX as i128);
+    let check = quote::quote_spanned! {expr.span()=> assert!(#in_range, #TAG_RANGE_ERROR); };
+    consts.extend(quote! {
+        const #ident: u32 = {
+            let value #ty = #expr;
+            #check
+            value as u32
+        };
+    });
+    Ok(Tag::Const(ident))
+}
+
+/// Tags of enum variants: `#[cerdito(tag = N)]`, the discriminant, or the previous tag plus
+/// one. All tags and alias tags must be unique. Tags given by integer literals are checked
+/// here, the others by const asserts.
+fn generate_tags(data: &syn::DataEnum, attrs: &attr::ContainerAttrs) -> syn::Result<EnumTags> {
+    let mut tags = Vec::new();
+    let mut consts = proc_macro2::TokenStream::new();
+    let mut errors: Option<syn::Error> = None;
+    let mut push_error = |error: syn::Error| match &mut errors {
+        Some(errors) => errors.combine(error),
+        None => errors = Some(error),
+    };
+    let repr = match &attrs.repr {
+        Some(repr) => quote!(#repr),
+        None => quote!(isize),
+    };
+    let mut used = std::collections::HashMap::new();
+    // all tags with their variants, for the uniqueness checks involving consts
+    let mut all: Vec<(syn::Ident, proc_macro2::Span, &syn::Ident)> = Vec::new();
+    let mut next: Result<Option<u32>, syn::Ident> = Ok(Some(0));
+    // the implicit tag following an invalid one is unknown, but not reported again
+    let mut after_error = false;
+    for (i, v) in data.variants.iter().enumerate() {
+        let variant_attrs = match attr::VariantAttrs::parse(&v.attrs) {
+            Ok(attrs) => attrs,
+            Err(error) => {
+                push_error(error);
+                after_error = true;
+                continue;
+            }
+        };
+        let ident = quote::format_ident!("__CERDITO_TAG_{}", i);
+        let (tag, span) = match (&variant_attrs.tag, &v.discriminant) {
+            (Some(expr), _) => (expr_tag(expr, None, ident, &mut consts), expr.span()),
+            (None, Some((_, expr))) => {
+                (expr_tag(expr, Some(&repr), ident, &mut consts), expr.span())
+            }
+            (None, None) if after_error => continue,
+            (None, None) => (
+                match &next {
+                    Ok(Some(value)) => Ok(Tag::Lit(*value)),
+                    Ok(None) => Err(syn::Error::new_spanned(&v.ident, TAG_RANGE_ERROR)),
+                    Err(prev) => {
+                        consts.extend(quote::quote_spanned! {v.ident.span()=>
+                            const #ident: u32 = {
+                                assert!(#prev < u32::MAX, #TAG_RANGE_ERROR);
+                                #prev + 1
+                            };
+                        });
+                        Ok(Tag::Const(ident))
+                    }
+                },
+                v.ident.span(),
+            ),
+        };
+        let tag = match tag {
+            Ok(tag) => tag,
+            Err(error) => {
+                push_error(error);
+                after_error = true;
+                continue;
+            }
+        };
+        after_error = false;
+        next = match &tag {
+            Tag::Lit(value) => Ok(value.checked_add(1)),
+            Tag::Const(ident) => Err(ident.clone()),
+        };
+        let mut aliases = Vec::new();
+        for (k, expr) in variant_attrs.alias_tags.iter().enumerate() {
+            let ident = quote::format_ident!("__CERDITO_TAG_{}_ALIAS_{}", i, k);
+            match expr_tag(expr, None, ident, &mut consts) {
+                Ok(alias) => aliases.push((alias, expr.span())),
+                Err(error) => push_error(error),
+            }
+        }
+        for (value, span) in
+            std::iter::once((&tag, span)).chain(aliases.iter().map(|(a, s)| (a, *s)))
+        {
+            match value {
+                Tag::Lit(value) => {
+                    if let Some(other) = used.insert(*value, v.ident.clone()) {
+                        push_error(syn::Error::new(
+                            span,
+                            format!("enum tag {} is already used by variant `{}`", value, other),
+                        ));
+                    }
+                }
+                Tag::Const(ident) => all.push((ident.clone(), span, &v.ident)),
+            }
+        }
+        tags.push(Tags {
+            tag,
+            aliases: aliases.into_iter().map(|(alias, _)| alias).collect(),
+        });
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+    // tags given by consts are compared with all other tags
+    let mut checks = proc_macro2::TokenStream::new();
+    for (k, (ident, span, variant)) in all.iter().enumerate() {
+        let message = |other: &syn::Ident| {
+            format!(
+                "enum tag of variant `{}` is already used by variant `{}`",
+                variant, other
+            )
+        };
+        for (other_ident, _, other) in &all[..k] {
+            let message = message(other);
+            checks.extend(quote::quote_spanned! {*span=>
+                assert!(#ident != #other_ident, #message);
+            });
+        }
+        for (value, other) in &used {
+            let message = message(other);
+            checks.extend(quote::quote_spanned! {*span=>
+                assert!(#ident != #value, #message);
+            });
+        }
+    }
+    if !checks.is_empty() {
+        consts.extend(quote! {
+            const _: () = { #checks };
+        });
+    }
+    Ok(EnumTags { tags, consts })
 }
 
 fn generate_encode_for_enum(
     data: &syn::DataEnum,
     name: &proc_macro2::Ident,
-) -> proc_macro2::TokenStream {
-    let name_str = name.to_string();
-    let tags = generate_tags(data);
-    let variant_codes: Vec<_> = data.variants.iter().zip(tags).enumerate().map(|(i, (v, t))| {
-        let variant_name = v.ident.clone();
-        let variant_name_str = v.ident.to_string();
-        let fields = get_fields(&v.fields);
-        let field_idents: Vec<_> = fields
-            .iter()
-            .map(|(_, ident, _, _)| ident.clone())
-            .collect();
-        let field_codes: Vec<_> = fields
-            .iter()
-            .map(|(_i, field_ident, field_name, _field_type)| {
-                quote! {
-                    _await!(encoder.encode_elem_begin(#i, Some(#field_name)))?;
-                    _await!(#field_ident.encode(encoder))?;
-                    _await!(encoder.encode_elem_end())?;
-                }
+    attrs: &attr::ContainerAttrs,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name_str = attrs.name(name);
+    let tags = generate_tags(data, attrs)?.tags;
+    let variant_codes = data
+        .variants
+        .iter()
+        .zip(tags)
+        .map(|(v, Tags { tag: t, .. })| {
+            let variant_name = v.ident.clone();
+            let variant_attrs = attr::VariantAttrs::parse(&v.attrs)?;
+            let variant_name_str = variant_attrs.name(&v.ident, attrs.rename_all);
+            let fields = get_fields(&v.fields, name, variant_attrs.rename_all)?;
+            let named_bindings = encode_bindings(&fields, true);
+            let unnamed_bindings = encode_bindings(&fields, false);
+            let field_codes = encode_field_codes(&fields);
+            let fields_len = encoded_len(&fields);
+            Ok(match &v.fields {
+                syn::Fields::Named(_) => quote! {
+                    Self::#variant_name { #(#named_bindings),* } => {
+                        _await!(encoder.encode_enum_begin(#t, 1, #name_str, #variant_name_str))?;
+                        _await!(encoder.encode_struct_begin(#fields_len, Some(#variant_name_str)))?;
+                        #(#field_codes)*
+                        _await!(encoder.encode_struct_end())?;
+                        _await!(encoder.encode_enum_end())?;
+                    }
+                },
+                syn::Fields::Unnamed(_) => quote! {
+                    Self::#variant_name(#(#unnamed_bindings),*) => {
+                        _await!(encoder.encode_enum_begin(#t, 1, #name_str, #variant_name_str))?;
+                        _await!(encoder.encode_struct_begin(#fields_len, Some(#variant_name_str)))?;
+                        #(#field_codes)*
+                        _await!(encoder.encode_struct_end())?;
+                        _await!(encoder.encode_enum_end())?;
+                    }
+                },
+                syn::Fields::Unit => quote! {
+                    Self::#variant_name => {
+                        _await!(encoder.encode_enum_begin(#t, 0, #name_str, #variant_name_str))?;
+                        _await!(encoder.encode_enum_end())?;
+                    }
+                },
             })
-            .collect();
-        let fields_len = fields.len();
-        match &v.fields {
-            syn::Fields::Named(_) => quote! {
-                Self::#variant_name { #(#field_idents),* } => {
-                    let __cerdito_enum_tag: u32 = (#t).try_into().unwrap(); //TODO: error
-                    _await!(encoder.encode_enum_begin(__cerdito_enum_tag, 1, #name_str, #variant_name_str))?;
-                    _await!(encoder.encode_struct_begin(#fields_len, None))?;
-                    #(#field_codes)*
-                    _await!(encoder.encode_struct_end())?;
-                    _await!(encoder.encode_enum_end())?;
-                }
-            },
-            syn::Fields::Unnamed(_) => quote! {
-                Self::#variant_name(#(#field_idents),*) => {
-                    let __cerdito_enum_tag: u32 = (#t).try_into().unwrap(); //TODO: error
-                    _await!(encoder.encode_enum_begin(__cerdito_enum_tag, 1, #name_str, #variant_name_str))?;
-                    _await!(encoder.encode_struct_begin(#fields_len, None))?;
-                    #(#field_codes)*
-                    _await!(encoder.encode_struct_end())?;
-                    _await!(encoder.encode_enum_end())?;
-                }
-            },
-            syn::Fields::Unit => quote! {
-                Self::#variant_name => {
-                    let __cerdito_enum_tag: u32 = (#t).try_into().unwrap(); //TODO: error
-                    _await!(encoder.encode_enum_begin(__cerdito_enum_tag, 0, #name_str, #variant_name_str))?;
-                    _await!(encoder.encode_enum_end())?;
-                }
-            },
-        }
-    }).collect();
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
 
-    quote! {
+    Ok(quote! {
         match self {
             #(#variant_codes)*
         }
         Ok(())
-    }
+    })
 }
 
 fn generate_decode_for_enum(
     data: &syn::DataEnum,
     name: &proc_macro2::Ident,
-) -> proc_macro2::TokenStream {
-    let name_str = name.to_string();
-    let tags = generate_tags(data);
-    let variant_codes: Vec<_> = data
+    attrs: &attr::ContainerAttrs,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name_str = attrs.name(name);
+    let tags = generate_tags(data, attrs)?.tags;
+    let variant_codes = data
         .variants
         .iter()
         .zip(tags)
-        .enumerate()
-        .map(|(_i, (v, t))| {
+        .map(|(v, Tags { tag, aliases })| {
             let variant_name = v.ident.clone();
-            let fields = get_fields(&v.fields);
-            let field_idents: Vec<_> = fields
-                .iter()
-                .map(|(_, ident, _, _)| ident.clone())
-                .collect();
-            let field_codes: Vec<_> = fields
-                .iter()
-                .map(|(i, field_ident, field_name, field_type)| {
-                    quote! {
-                        _await!(decoder.decode_elem_begin(#i, Some(#field_name)))?;
-                        let #field_ident = if #i < __cerdito_len {
-                            _await!(<#field_type as ::cerdito::Decode>::decode(decoder))?
-                        } else { // new program, old data
-                            <#field_type>::default()
-                        };
-                        _await!(decoder.decode_elem_end())?;
-                    }
-                })
-                .collect();
+            let t = quote! { #tag #(| #aliases)* };
+            let variant_attrs = attr::VariantAttrs::parse(&v.attrs)?;
+            let variant_name_str = variant_attrs.name(&v.ident, attrs.rename_all);
+            let fields = get_fields(&v.fields, name, variant_attrs.rename_all)?;
+            let field_idents = field_idents(&fields);
+            let field_codes = decode_field_codes(&fields);
 
             let field_defaults: Vec<_> = fields
                 .iter()
-                .map(|(_i, field_ident, _field_name, field_type)| {
+                .map(|f| {
+                    let field_ident = &f.ident;
+                    let missing = missing_value(f);
                     quote! {
-                        let #field_ident = <#field_type>::default();
+                        let #field_ident = #missing;
                     }
                 })
                 .collect();
+            // a unit-encoded variant has none of its fields
+            let missing_required = fields
+                .iter()
+                .find(|f| fails_when_missing(f))
+                .map(missing_value);
+            let unit_named = match &missing_required {
+                Some(error) => error.clone(),
+                None => quote! {
+                    #(#field_defaults)*
+                    Self::#variant_name { #(#field_idents),* }
+                },
+            };
+            let unit_unnamed = match missing_required {
+                Some(error) => error,
+                None => quote! {
+                    #(#field_defaults)*
+                    Self::#variant_name(#(#field_idents),*)
+                },
+            };
 
-            let fields_len = fields.len();
+            let fields_len = decoded_len(&fields);
 
             let compat = quote! {
                 // old program, new data
@@ -334,22 +935,21 @@ fn generate_decode_for_enum(
                 }
             };
 
-            match &v.fields {
+            Ok(match &v.fields {
                 syn::Fields::Named(_) => quote! {
                     #t => {
                         match __cerdito_enum_len {
                             0 => {
-                                #(#field_defaults)*
-                                Self::#variant_name { #(#field_idents),* }
+                                #unit_named
                             }
                             1 => {
-                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, None))?;
+                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, Some(#variant_name_str)))?;
                                 #(#field_codes)*
                                 #compat
                                 _await!(decoder.decode_struct_end())?;
                                 Self::#variant_name { #(#field_idents),* }
                             }
-                            _ => unreachable!(),
+                            _ => return Err(decoder.annotate(<__CerditoDecoderTypeParam::Error as __cerdito::Error>::invalid_length(1, __cerdito_enum_len))),
                         }
                     }
                 },
@@ -357,17 +957,16 @@ fn generate_decode_for_enum(
                     #t => {
                         match __cerdito_enum_len {
                             0 => {
-                                #(#field_defaults)*
-                                Self::#variant_name(#(#field_idents),*)
+                                #unit_unnamed
                             }
                             1 => {
-                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, None))?;
+                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, Some(#variant_name_str)))?;
                                 #(#field_codes)*
                                 #compat
                                 _await!(decoder.decode_struct_end())?;
                                 Self::#variant_name(#(#field_idents),*)
                             }
-                            _ => unreachable!(),
+                            _ => return Err(decoder.annotate(<__CerditoDecoderTypeParam::Error as __cerdito::Error>::invalid_length(1, __cerdito_enum_len))),
                         }
                     }
                 },
@@ -378,26 +977,26 @@ fn generate_decode_for_enum(
                                 Self::#variant_name
                             }
                             1 => {
-                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, None))?;
+                                let __cerdito_len = _await!(decoder.decode_struct_begin(#fields_len, Some(#variant_name_str)))?;
                                 #compat
                                 _await!(decoder.decode_struct_end())?;
                                 Self::#variant_name
                             }
-                            _ => unreachable!(),
+                            _ => return Err(decoder.annotate(<__CerditoDecoderTypeParam::Error as __cerdito::Error>::invalid_length(1, __cerdito_enum_len))),
                         }
                     }
                 },
-            }
+            })
         })
-        .collect();
+        .collect::<syn::Result<Vec<_>>>()?;
 
-    quote! {
+    Ok(quote! {
         let (__cerdito_enum_tag, __cerdito_enum_len) = _await!(decoder.decode_enum_begin(#name_str))?;
-        let __cerdito_enum_value = match __cerdito_enum_tag.try_into().unwrap() { // TODO: error
+        let __cerdito_enum_value = match __cerdito_enum_tag {
                 #(#variant_codes)*
-                _ => panic!("Enum {:?} doesn't support variant {}", #name_str, __cerdito_enum_tag),
+                _ => return Err(decoder.annotate(<__CerditoDecoderTypeParam::Error as __cerdito::Error>::unknown_variant(#name_str, __cerdito_enum_tag))),
         };
         _await!(decoder.decode_enum_end())?;
         Ok(__cerdito_enum_value)
-    }
+    })
 }
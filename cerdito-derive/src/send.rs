@@ -0,0 +1,587 @@
+//! `Send` variants of `#[_async]` code: `SendDecoder`, `SendEncode`, ... whose async methods
+//! return `Send` futures, generated from the base traits and impls.
+
+use quote::{format_ident, quote, ToTokens};
+
+/// Traits whose bounds become bounds on their `Send` variants.
+const SEND_TRAITS: &[(&str, &str)] = &[
+    ("Decoder", "SendDecoder"),
+    ("Encoder", "SendEncoder"),
+    ("Decode", "SendDecode"),
+    ("Encode", "SendEncode"),
+    ("Reader", "SendReader"),
+    ("Writer", "SendWriter"),
+];
+
+/// Body of a `Send` variant: `_await!` calls the `*_send` functions, like `build_async` replaces
+/// it with `_await_async` in the async variant.
+pub fn send_body(body: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    body.into_iter()
+        .map(|token| match token {
+            proc_macro2::TokenTree::Group(group) => {
+                let mut new_group =
+                    proc_macro2::Group::new(group.delimiter(), send_body(group.stream()));
+                new_group.set_span(group.span());
+                new_group.into()
+            }
+            proc_macro2::TokenTree::Ident(ident) if ident == "_await" => {
+                proc_macro2::Ident::new("_await_send", ident.span()).into()
+            }
+            token => token,
+        })
+        .collect()
+}
+
+/// `_await!` of `Send` variants: calls the `*_send` method or function and awaits it.
+pub fn await_send(input: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let mut expr: syn::Expr = syn::parse2(input)?;
+    match &mut expr {
+        syn::Expr::MethodCall(call) => call.method = format_ident!("{}_send", call.method),
+        syn::Expr::Call(syn::ExprCall { func, .. }) => match &mut **func {
+            syn::Expr::Path(syn::ExprPath { qself, path, .. }) => {
+                // `<T as Decode>::decode` becomes `<T as SendDecode>::decode_send`
+                if let Some(qself) = qself.as_ref().filter(|q| q.position > 0) {
+                    let segment = &mut path.segments[qself.position - 1];
+                    if let Some((_, to)) =
+                        SEND_TRAITS.iter().find(|(from, _)| segment.ident == from)
+                    {
+                        segment.ident = syn::Ident::new(to, segment.ident.span());
+                    }
+                }
+                let last = path.segments.last_mut().unwrap();
+                last.ident = format_ident!("{}_send", last.ident);
+            }
+            func => {
+                return Err(syn::Error::new_spanned(
+                    func,
+                    "`_await!` can only be applied to a method or explicit function call",
+                ))
+            }
+        },
+        expr => {
+            return Err(syn::Error::new_spanned(
+                expr,
+                "`_await!` can only be applied to a function or method call",
+            ))
+        }
+    }
+    Ok(quote! { #expr.await })
+}
+
+fn rename_trait(path: &mut syn::Path) {
+    if let Some(last) = path.segments.last_mut() {
+        if let Some((_, to)) = SEND_TRAITS.iter().find(|(from, _)| last.ident == from) {
+            last.ident = syn::Ident::new(to, last.ident.span());
+        }
+    }
+}
+
+/// `T: Decode` becomes `T: SendDecode` and so on.
+fn rename_bounds(generics: &mut syn::Generics) {
+    let bounds = generics
+        .params
+        .iter_mut()
+        .filter_map(|param| match param {
+            syn::GenericParam::Type(param) => Some(&mut param.bounds),
+            _ => None,
+        })
+        .chain(
+            generics
+                .where_clause
+                .iter_mut()
+                .flat_map(|clause| clause.predicates.iter_mut())
+                .filter_map(|predicate| match predicate {
+                    syn::WherePredicate::Type(predicate) => Some(&mut predicate.bounds),
+                    _ => None,
+                }),
+        );
+    for bounds in bounds {
+        for bound in bounds {
+            if let syn::TypeParamBound::Trait(bound) = bound {
+                rename_trait(&mut bound.path);
+            }
+        }
+    }
+}
+
+/// Adds the predicates of the `Send` variant, `T: Send` to the existing bounds of `T`.
+fn add_predicates(generics: &mut syn::Generics, predicates: Vec<syn::WherePredicate>) {
+    for predicate in predicates {
+        if let syn::WherePredicate::Type(predicate) = &predicate {
+            let ty = predicate.bounded_ty.to_token_stream().to_string();
+            let bounded = generics
+                .where_clause
+                .iter_mut()
+                .flat_map(|clause| clause.predicates.iter_mut())
+                .find_map(|p| match p {
+                    syn::WherePredicate::Type(p)
+                        if p.bounded_ty.to_token_stream().to_string() == ty =>
+                    {
+                        Some(&mut p.bounds)
+                    }
+                    _ => None,
+                });
+            let bounds = match bounded {
+                Some(bounds) => Some(bounds),
+                None => generics
+                    .type_params_mut()
+                    .find(|param| param.ident == ty)
+                    .map(|param| &mut param.bounds),
+            };
+            if let Some(bounds) = bounds {
+                bounds.extend(predicate.bounds.iter().cloned());
+                continue;
+            }
+        }
+        generics.make_where_clause().predicates.push(predicate);
+    }
+}
+
+fn is_async_attr(attr: &syn::Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "_async")
+}
+
+/// Whether the `Send` variant is a trait method with an optional default body, or an
+/// `async fn` of an impl or module.
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Trait,
+    Impl,
+}
+
+/// Names of the async functions of a trait or impl: `f_async` of an `#[_async] fn f`, and the
+/// hand-written `async fn f_async`.
+fn async_names<'a>(
+    fns: impl Iterator<Item = (&'a [syn::Attribute], &'a syn::Signature)>,
+) -> Vec<String> {
+    fns.filter_map(|(attrs, sig)| match attrs.iter().any(is_async_attr) {
+        true => Some(format!("{}_async", sig.ident)),
+        false => sig.asyncness.map(|_| sig.ident.to_string()),
+    })
+    .collect()
+}
+
+/// Renames the calls of the async functions of the same trait or impl to their `Send`
+/// variants in a hand-written async function, e.g. `T::decode_async(decoder)` to
+/// `T::decode_send(decoder)`. Other identifiers are kept.
+struct RenameCalls<'a>(&'a [String]);
+
+impl RenameCalls<'_> {
+    fn rename(&self, ident: &mut syn::Ident) {
+        let name = ident.to_string();
+        if let Some(name) = name
+            .strip_suffix("_async")
+            .filter(|_| self.0.contains(&name))
+        {
+            *ident = format_ident!("{}_send", name, span = ident.span());
+        }
+    }
+}
+
+impl syn::visit_mut::VisitMut for RenameCalls<'_> {
+    fn visit_expr_method_call_mut(&mut self, call: &mut syn::ExprMethodCall) {
+        self.rename(&mut call.method);
+        syn::visit_mut::visit_expr_method_call_mut(self, call);
+    }
+
+    fn visit_expr_call_mut(&mut self, call: &mut syn::ExprCall) {
+        if let syn::Expr::Path(syn::ExprPath { path, .. }) = &mut *call.func {
+            if let Some(last) = path.segments.last_mut() {
+                self.rename(&mut last.ident);
+            }
+        }
+        syn::visit_mut::visit_expr_call_mut(self, call);
+    }
+}
+
+/// `Send` variant of an `#[_async]` function or of an `async fn *_async`, `None` for other
+/// functions: they are inherited from the base trait or impl. `async_names` are the async
+/// functions of the same trait or impl, see `RenameCalls`.
+fn send_fn(
+    attrs: &[syn::Attribute],
+    vis: &syn::Visibility,
+    sig: &syn::Signature,
+    block: Option<proc_macro2::TokenStream>,
+    context: Context,
+    async_names: &[String],
+) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let generated = attrs.iter().any(is_async_attr);
+    if !generated && sig.asyncness.is_none() {
+        return Ok(None);
+    }
+    let attrs = attrs
+        .iter()
+        .filter(|a| !is_async_attr(a) && !a.path().is_ident("doc"));
+    let mut sig = sig.clone();
+    let name = sig.ident.to_string();
+    let name = name.strip_suffix("_async").unwrap_or(&name);
+    sig.ident = format_ident!("{}_send", name, span = sig.ident.span());
+    rename_bounds(&mut sig.generics);
+    let block = match block {
+        Some(block) if generated => Some(send_body(block)),
+        // a hand-written async fn calls the async variants of other functions
+        Some(block) => {
+            let mut block: syn::Block = syn::parse2(block)?;
+            syn::visit_mut::VisitMut::visit_block_mut(&mut RenameCalls(async_names), &mut block);
+            Some(block.to_token_stream())
+        }
+        None => None,
+    };
+    Ok(Some(match context {
+        Context::Trait => {
+            sig.asyncness = None;
+            let output = match &sig.output {
+                syn::ReturnType::Default => quote!(()),
+                syn::ReturnType::Type(_, ty) => ty.to_token_stream(),
+            };
+            sig.output = syn::parse_quote! {
+                -> impl ::core::future::Future<Output = #output> + ::core::marker::Send
+            };
+            match block {
+                Some(block) => quote! { #(#attrs)* #vis #sig { async move #block } },
+                None => quote! { #(#attrs)* #vis #sig; },
+            }
+        }
+        Context::Impl => {
+            sig.asyncness = Some(Default::default());
+            quote! { #(#attrs)* #vis #sig #block }
+        }
+    }))
+}
+
+/// `m! {args}` in a trait or impl becomes `m! {#[send_variant(..)] args}`, so the macro must
+/// pass leading attributes on to the `#[_async]` functions it generates.
+fn send_macro(mac: &syn::Macro, context: Context) -> proc_macro2::TokenStream {
+    let mut mac = mac.clone();
+    let tokens = mac.tokens;
+    mac.tokens = match context {
+        Context::Trait => quote! { #[send_variant(trait_item)] #tokens },
+        Context::Impl => quote! { #[send_variant(impl_item)] #tokens },
+    };
+    match mac.delimiter {
+        syn::MacroDelimiter::Brace(_) => quote! { #mac },
+        _ => quote! { #mac; },
+    }
+}
+
+fn non_doc_attrs(attrs: &[syn::Attribute]) -> impl Iterator<Item = &syn::Attribute> {
+    attrs.iter().filter(|a| !a.path().is_ident("doc"))
+}
+
+/// `trait SendX: X` with the `Send` variants of the async methods.
+fn send_trait(
+    item: &syn::ItemTrait,
+    predicates: Vec<syn::WherePredicate>,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &item.ident;
+    let send_name = format_ident!("Send{}", name);
+    let doc = format!("`{}` whose async methods return `Send` futures.", name);
+    let attrs = non_doc_attrs(&item.attrs);
+    let vis = &item.vis;
+    let mut generics = item.generics.clone();
+    generics.make_where_clause().predicates.extend(predicates);
+    let (_, type_generics, where_clause) = generics.split_for_impl();
+    let params = &generics.params;
+    let names = async_names(item.items.iter().filter_map(|item| match item {
+        syn::TraitItem::Fn(f) => Some((&f.attrs[..], &f.sig)),
+        _ => None,
+    }));
+    let items = item
+        .items
+        .iter()
+        .map(|item| match item {
+            syn::TraitItem::Fn(f) => send_fn(
+                &f.attrs,
+                &syn::Visibility::Inherited,
+                &f.sig,
+                f.default.as_ref().map(ToTokens::to_token_stream),
+                Context::Trait,
+                &names,
+            ),
+            syn::TraitItem::Macro(m) => Ok(Some(send_macro(&m.mac, Context::Trait))),
+            _ => Ok(None),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    Ok(quote! {
+        #[doc = #doc]
+        #(#attrs)*
+        #vis trait #send_name<#params>: #name #type_generics #where_clause {
+            #(#items)*
+        }
+    })
+}
+
+/// The impl of the `Send` variant of the trait, or an inherent impl with the `Send` variants
+/// of the async functions.
+fn send_impl(
+    item: &syn::ItemImpl,
+    predicates: Vec<syn::WherePredicate>,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut generics = item.generics.clone();
+    rename_bounds(&mut generics);
+    add_predicates(&mut generics, predicates);
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let attrs = non_doc_attrs(&item.attrs);
+    let self_ty = &item.self_ty;
+    let names = async_names(item.items.iter().filter_map(|item| match item {
+        syn::ImplItem::Fn(f) => Some((&f.attrs[..], &f.sig)),
+        _ => None,
+    }));
+    let items = item
+        .items
+        .iter()
+        .map(|item| match item {
+            syn::ImplItem::Fn(f) => send_fn(
+                &f.attrs,
+                &f.vis,
+                &f.sig,
+                Some(f.block.to_token_stream()),
+                Context::Impl,
+                &names,
+            ),
+            syn::ImplItem::Macro(m) => Ok(Some(send_macro(&m.mac, Context::Impl))),
+            _ => Ok(None),
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+    let items = items.iter().flatten().collect::<Vec<_>>();
+    Ok(match &item.trait_ {
+        Some((_, path, _)) => {
+            let mut path = path.clone();
+            rename_trait(&mut path);
+            quote! {
+                #(#attrs)*
+                impl #impl_generics #path for #self_ty #where_clause {
+                    #(#items)*
+                }
+            }
+        }
+        None if items.is_empty() => quote! {},
+        None => quote! {
+            #(#attrs)*
+            impl #impl_generics #self_ty #where_clause {
+                #(#items)*
+            }
+        },
+    })
+}
+
+/// `#[send_variant]` on a trait, impl or function: the item followed by its `Send` variant,
+/// see `send_trait`, `send_impl` and `send_fn`. The arguments are where predicates of the
+/// variant, e.g. `Self: Send` for a trait.
+///
+/// In a trait or impl generated by `#[send_variant]`, `#[send_variant(trait_item)]` and
+/// `#[send_variant(impl_item)]` replace a function generated by a macro with its variant.
+pub fn send_variant(
+    attr: proc_macro2::TokenStream,
+    input: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Ok(context) = syn::parse2::<syn::Ident>(attr.clone()) {
+        let context = match context.to_string().as_str() {
+            "trait_item" => Context::Trait,
+            "impl_item" => Context::Impl,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    context,
+                    "unknown item context, expected `trait_item` or `impl_item`",
+                ))
+            }
+        };
+        // the body is kept as is: parsing it would drop the invisible groups of macro
+        // fragments, e.g. around a `$closure:expr` that is called
+        let mut tokens: Vec<proc_macro2::TokenTree> = input.into_iter().collect();
+        let block = match tokens.last() {
+            Some(proc_macro2::TokenTree::Group(group))
+                if group.delimiter() == proc_macro2::Delimiter::Brace =>
+            {
+                tokens.pop().map(Into::into)
+            }
+            _ => None,
+        };
+        let decl = proc_macro2::TokenStream::from_iter(tokens);
+        let f: syn::TraitItemFn = syn::parse2(match block {
+            Some(_) => quote! { #decl {} },
+            None => decl,
+        })?;
+        let vis = syn::Visibility::Inherited;
+        let names = async_names(std::iter::once((&f.attrs[..], &f.sig)));
+        return Ok(send_fn(&f.attrs, &vis, &f.sig, block, context, &names)?.unwrap_or_default());
+    }
+    let predicates = syn::parse::Parser::parse2(
+        syn::punctuated::Punctuated::<syn::WherePredicate, syn::Token![,]>::parse_terminated,
+        attr,
+    )?
+    .into_iter()
+    .collect();
+    let item: syn::Item = syn::parse2(input)?;
+    let variant = match &item {
+        syn::Item::Trait(item) => send_trait(item, predicates)?,
+        syn::Item::Impl(item) => send_impl(item, predicates)?,
+        syn::Item::Fn(f) => {
+            let mut f = f.clone();
+            add_predicates(&mut f.sig.generics, predicates);
+            let names = async_names(std::iter::once((&f.attrs[..], &f.sig)));
+            send_fn(
+                &f.attrs,
+                &f.vis,
+                &f.sig,
+                Some(f.block.to_token_stream()),
+                Context::Impl,
+                &names,
+            )?
+            .unwrap_or_default()
+        }
+        item => {
+            return Err(syn::Error::new_spanned(
+                item,
+                "`send_variant` applies to traits, impls and functions",
+            ))
+        }
+    };
+    Ok(quote! {
+        #item
+        #variant
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_tokens(actual: proc_macro2::TokenStream, expected: proc_macro2::TokenStream) {
+        assert_eq!(actual.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_send_body() {
+        // only `_await` is renamed, also in nested groups
+        let body = quote! {
+            let decode_async = _await!(<T as Decode>::decode(decoder))?;
+            { _await!(self.read_async(decode_async)) }
+        };
+        assert_tokens(
+            send_body(body),
+            quote! {
+                let decode_async = _await_send!(<T as Decode>::decode(decoder))?;
+                { _await_send!(self.read_async(decode_async)) }
+            },
+        );
+    }
+
+    #[test]
+    fn test_await_send() {
+        let expand = |input| await_send(input).unwrap();
+        assert_tokens(
+            expand(quote!(decoder.decode_u8())),
+            quote!(decoder.decode_u8_send().await),
+        );
+        assert_tokens(
+            expand(quote!(u8::decode(decoder))),
+            quote!(u8::decode_send(decoder).await),
+        );
+        assert_tokens(
+            expand(quote!(<T as __cerdito::Decode>::decode(decoder))),
+            quote!(<T as __cerdito::SendDecode>::decode_send(decoder).await),
+        );
+        // traits other than the base traits are kept
+        assert_tokens(
+            expand(quote!(<T as Other>::decode(decoder))),
+            quote!(<T as Other>::decode_send(decoder).await),
+        );
+        assert!(await_send(quote!(decoder)).is_err());
+        assert!(await_send(quote!((self.f)(decoder))).is_err());
+    }
+
+    #[test]
+    fn test_send_trait() {
+        let expanded = send_variant(
+            quote!(Self: Send),
+            quote! {
+                pub trait Reader {
+                    fn read(&mut self) -> u8;
+                    async fn read_async(&mut self) -> u8 {
+                        self.read()
+                    }
+                }
+            },
+        )
+        .unwrap();
+        assert_tokens(
+            expanded,
+            quote! {
+                pub trait Reader {
+                    fn read(&mut self) -> u8;
+                    async fn read_async(&mut self) -> u8 {
+                        self.read()
+                    }
+                }
+                #[doc = "`Reader` whose async methods return `Send` futures."]
+                pub trait SendReader<>: Reader where Self: Send {
+                    fn read_send(&mut self)
+                        -> impl ::core::future::Future<Output = u8> + ::core::marker::Send
+                    {
+                        async move { self.read() }
+                    }
+                }
+            },
+        );
+    }
+
+    #[test]
+    fn test_send_impl() {
+        // calls of the async functions of the impl are renamed, other identifiers are kept
+        let expanded = send_variant(
+            quote!(),
+            quote! {
+                impl<T: Decode> Decode for Box<T> {
+                    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                        Ok(Box::new(T::decode(decoder)?))
+                    }
+                    async fn decode_async<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                        let skip_async = other_async(decoder);
+                        Ok(Box::new(Box::pin(T::decode_async(decoder)).await?))
+                    }
+                }
+            },
+        )
+        .unwrap();
+        let expected_send = quote! {
+            impl<T: SendDecode> SendDecode for Box<T> {
+                async fn decode_send<D: SendDecoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                    let skip_async = other_async(decoder);
+                    Ok(Box::new(Box::pin(T::decode_send(decoder)).await?))
+                }
+            }
+        };
+        assert!(expanded.to_string().ends_with(&expected_send.to_string()));
+    }
+
+    #[test]
+    fn test_send_fn() {
+        let expanded = send_variant(
+            quote!(I: Send),
+            quote! {
+                #[_async]
+                fn encode_all<E: Encoder, I: Iterator>(encoder: &mut E, iter: I) -> Result<(), E::Error> {
+                    _await!(encoder.encode_all(iter))
+                }
+            },
+        )
+        .unwrap();
+        let expected_send = quote! {
+            async fn encode_all_send<E: SendEncoder, I: Iterator + Send>(encoder: &mut E, iter: I) -> Result<(), E::Error> {
+                _await_send!(encoder.encode_all(iter))
+            }
+        };
+        assert!(expanded.to_string().ends_with(&expected_send.to_string()));
+        assert!(send_variant(
+            quote!(),
+            quote!(
+                struct S;
+            )
+        )
+        .is_err());
+    }
+}
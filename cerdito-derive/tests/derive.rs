@@ -0,0 +1,709 @@
+use build_async::*;
+use cerdito::{Decode, Encode};
+use rustbif::{Decoder, Encoder, Error, SliceReader, UnexpectedEof};
+
+fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut encoder = Encoder::new(Vec::new());
+    value.encode(&mut encoder).unwrap();
+    encoder.writer
+}
+
+fn decode<T: Decode>(bytes: &[u8]) -> Result<T, Error<UnexpectedEof>> {
+    T::decode(&mut Decoder::new(SliceReader::new(bytes)))
+}
+
+/// Encodes and decodes `value`, checking that the async functions produce the same bytes.
+fn roundtrip<T: Encode + Decode>(value: &T) -> T {
+    let bytes = encode(value);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let decoded = runtime.block_on(async {
+        let mut encoder = Encoder::new(Vec::new());
+        value.encode_async(&mut encoder).await.unwrap();
+        assert_eq!(encoder.writer, bytes);
+        let mut decoder = Decoder::new(SliceReader::new(&bytes));
+        T::decode_async(&mut decoder).await.unwrap()
+    });
+    assert_eq!(encode(&decoded), encode(&decode::<T>(&bytes).unwrap()));
+    decoded
+}
+
+/// Encodes a `u64` as 8 little-endian bytes, for the `with` attribute.
+mod fixed_bytes {
+    use build_async::*;
+    use cerdito::{Decoder, Encoder, Error, SendDecoder, SendEncoder};
+    use cerdito_derive::{_await_send, send_variant};
+
+    #[send_variant]
+    #[_async]
+    pub fn encode<E: Encoder>(value: &u64, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encoder.encode_binary(&value.to_le_bytes()))
+    }
+    #[send_variant]
+    #[_async]
+    pub fn decode<D: Decoder>(decoder: &mut D) -> Result<u64, D::Error> {
+        let bytes = _await!(decoder.decode_binary(Some(8)))?;
+        let len = bytes.len();
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| decoder.annotate(D::Error::invalid_length(8, len)))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+#[test]
+fn test_arrays() {
+    // elements don't need `Default`
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct NoDefault(String);
+
+    let value = [NoDefault("a".into()), NoDefault("b".into())];
+    assert_eq!(decode::<[NoDefault; 2]>(&encode(&value)).unwrap(), value);
+    let r = decode::<[NoDefault; 3]>(&encode(&value));
+    assert!(matches!(
+        r.unwrap_err().root(),
+        Error::InvalidLength {
+            expected: 3,
+            got: 2
+        }
+    ));
+    let mut truncated = encode(&value);
+    truncated.pop();
+    let r = decode::<[NoDefault; 2]>(&truncated);
+    assert!(matches!(r.unwrap_err().root(), Error::Io(UnexpectedEof)));
+
+    // new program, old data: a missing array is filled with `Default` of its elements,
+    // for any `N`, and fails to decode if they have no default
+    #[derive(Debug, Encode)]
+    struct V1 {
+        a: u8,
+    }
+    #[allow(dead_code)]
+    #[derive(Debug, Decode)]
+    struct V2 {
+        a: u8,
+        b: [NoDefault; 40],
+    }
+    #[derive(Debug, Decode)]
+    struct V3 {
+        a: u8,
+        #[cerdito(default = std::array::from_fn(|i| NoDefault(i.to_string())))]
+        b: [NoDefault; 40],
+        #[cerdito(skip)]
+        c: [[String; 2]; 33],
+        d: Option<[NoDefault; 2]>,
+    }
+    #[derive(Debug, Decode)]
+    struct V4 {
+        a: u8,
+        b: [u8; 4],
+        c: [[String; 2]; 40],
+    }
+    #[allow(dead_code)]
+    #[derive(Debug, Decode)]
+    struct V5<T> {
+        a: u8,
+        b: [T; 2],
+    }
+    #[derive(Debug, Decode)]
+    #[cerdito(bound(decode = "T: Decode + Default"))]
+    struct V6<T> {
+        a: u8,
+        b: [T; 40],
+    }
+    let r = decode::<V2>(&encode(&V1 { a: 7 }));
+    assert!(matches!(r.unwrap_err().root(), Error::MissingField(name) if name == "b"));
+    let v = decode::<V3>(&encode(&V1 { a: 7 })).unwrap();
+    assert_eq!(v.a, 7);
+    assert_eq!(v.b[39], NoDefault("39".into()));
+    assert!(v.c.iter().flatten().all(String::is_empty));
+    assert!(v.d.is_none());
+    let v = decode::<V4>(&encode(&V1 { a: 7 })).unwrap();
+    assert_eq!((v.a, v.b), (7, [0; 4]));
+    assert!(v.c.iter().flatten().all(String::is_empty));
+    let v = decode::<V4>(&encode(&(7_u8, [1_u8, 2, 3, 4]))).unwrap();
+    assert_eq!(v.b, [1, 2, 3, 4]);
+    // a generic element has a default only if the impl is bounded by `Default`
+    let r = decode::<V5<u16>>(&encode(&V1 { a: 7 }));
+    assert!(matches!(r.unwrap_err().root(), Error::MissingField(name) if name == "b"));
+    let v = decode::<V6<u16>>(&encode(&V1 { a: 7 })).unwrap();
+    assert_eq!((v.a, v.b), (7, [0; 40]));
+}
+
+#[test]
+fn test_field_defaults() {
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct NoDefault(u8);
+
+    #[derive(Debug, Encode)]
+    struct V1 {
+        a: u8,
+    }
+    #[derive(Debug, PartialEq, Decode)]
+    struct V2 {
+        a: u8,
+        #[cerdito(default = String::from("none"))]
+        b: String,
+        #[cerdito(default = NoDefault(42))]
+        c: NoDefault,
+    }
+    #[derive(Debug, PartialEq, Decode)]
+    struct V3 {
+        a: u8,
+        #[cerdito(required)]
+        b: String,
+    }
+    #[derive(Debug, PartialEq, Decode)]
+    struct T2(u8, #[cerdito(required)] NoDefault);
+
+    let bytes = encode(&V1 { a: 7 });
+    assert_eq!(
+        decode::<V2>(&bytes).unwrap(),
+        V2 {
+            a: 7,
+            b: "none".into(),
+            c: NoDefault(42)
+        }
+    );
+    let r = decode::<V3>(&bytes);
+    assert!(matches!(r.unwrap_err().root(), Error::MissingField(name) if name == "b"));
+    // the error carries the position and path of the missing field
+    assert_eq!(
+        decode::<V3>(&bytes).unwrap_err().to_string(),
+        "missing field b at byte 1 (V3.b)"
+    );
+    let r = decode::<T2>(&bytes);
+    assert!(matches!(r.unwrap_err().root(), Error::MissingField(name) if name == "1"));
+
+    let bytes = encode(&(7_u8, String::from("b"), NoDefault(1)));
+    assert_eq!(
+        decode::<V3>(&bytes).unwrap(),
+        V3 {
+            a: 7,
+            b: "b".into()
+        }
+    );
+    assert_eq!(decode::<V2>(&bytes).unwrap().c, NoDefault(1));
+    let bytes = encode(&(7_u8, NoDefault(1)));
+    assert_eq!(decode::<T2>(&bytes).unwrap(), T2(7, NoDefault(1)));
+
+    // unit-encoded variant of an enum whose variant gained fields
+    #[derive(Debug, Encode)]
+    enum E1 {
+        A,
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    enum E2 {
+        A(#[cerdito(default = 5)] u8),
+    }
+    #[derive(Debug, PartialEq, Decode)]
+    enum E3 {
+        A {
+            #[cerdito(required)]
+            a: u8,
+        },
+    }
+    let bytes = encode(&E1::A);
+    assert_eq!(decode::<E2>(&bytes).unwrap(), E2::A(5));
+    let r = decode::<E3>(&bytes);
+    assert!(matches!(r.unwrap_err().root(), Error::MissingField(name) if name == "a"));
+    let bytes = encode(&E2::A(1));
+    assert_eq!(decode::<E3>(&bytes).unwrap(), E3::A { a: 1 });
+}
+
+#[test]
+fn test_skipped_fields() {
+    use std::sync::Mutex;
+
+    #[derive(Debug, Encode, Decode)]
+    struct Cached {
+        a: u8,
+        #[cerdito(skip)]
+        cache: Mutex<Option<u64>>,
+        b: String,
+        #[cerdito(skip, default = 99)]
+        hash: u64,
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Tuple(u8, #[cerdito(skip)] Vec<u8>, u16);
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    enum Enum {
+        A(#[cerdito(skip)] u8, String),
+        B {
+            #[cerdito(skip)]
+            a: u8,
+            b: u16,
+        },
+    }
+
+    let value = Cached {
+        a: 1,
+        cache: Mutex::new(Some(5)),
+        b: "b".into(),
+        hash: 7,
+    };
+    let bytes = encode(&value);
+    assert_eq!(bytes, encode(&(1_u8, String::from("b"))));
+    let value = decode::<Cached>(&bytes).unwrap();
+    assert_eq!((value.a, value.b.as_str(), value.hash), (1, "b", 99));
+    assert_eq!(*value.cache.lock().unwrap(), None);
+
+    let bytes = encode(&Tuple(1, vec![2], 3));
+    assert_eq!(bytes, encode(&(1_u8, 3_u16)));
+    assert_eq!(decode::<Tuple>(&bytes).unwrap(), Tuple(1, vec![], 3));
+
+    let bytes = encode(&Enum::A(1, "a".into()));
+    assert_eq!(decode::<Enum>(&bytes).unwrap(), Enum::A(0, "a".into()));
+    let bytes = encode(&Enum::B { a: 1, b: 2 });
+    assert_eq!(decode::<Enum>(&bytes).unwrap(), Enum::B { a: 0, b: 2 });
+
+    // skipping in one direction only
+    #[derive(Debug, Encode)]
+    struct Writer {
+        a: u8,
+        #[cerdito(skip_encoding)]
+        _local: u8,
+        b: u8,
+    }
+    #[derive(Debug, PartialEq, Decode)]
+    struct Reader {
+        a: u8,
+        #[cerdito(skip_decoding, default = 3)]
+        c: u8,
+        b: u8,
+    }
+    let bytes = encode(&Writer {
+        a: 1,
+        _local: 2,
+        b: 4,
+    });
+    assert_eq!(
+        decode::<Reader>(&bytes).unwrap(),
+        Reader { a: 1, c: 3, b: 4 }
+    );
+}
+
+#[test]
+fn test_field_codecs() {
+    use std::time::{Duration, SystemTime};
+
+    #[_async]
+    fn encode_secs<E: cerdito::Encoder>(
+        value: &SystemTime,
+        encoder: &mut E,
+    ) -> Result<(), E::Error> {
+        let secs = value
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(<E::Error as cerdito::Error>::custom)?
+            .as_secs();
+        _await!(secs.encode(encoder))
+    }
+    #[_async]
+    fn decode_secs<D: cerdito::Decoder>(decoder: &mut D) -> Result<SystemTime, D::Error> {
+        let secs = _await!(u64::decode(decoder))?;
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Entry {
+        #[cerdito(with = fixed_bytes)]
+        hash: u64,
+        #[cerdito(encode_with = encode_secs, decode_with = decode_secs)]
+        #[cerdito(default = SystemTime::UNIX_EPOCH)]
+        modified: SystemTime,
+    }
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let entry = Entry {
+        hash: 0x0102,
+        modified,
+    };
+    assert_eq!(
+        encode(&entry),
+        encode(&(
+            cerdito::ByteArr(0x0102_u64.to_le_bytes()),
+            1_700_000_000_u64
+        ))
+    );
+    assert_eq!(roundtrip(&entry), entry);
+    let r = decode::<Entry>(&encode(&(vec![1_u8],)));
+    assert!(matches!(r.unwrap_err().root(), Error::BadBytesHeader));
+}
+
+#[test]
+fn test_transparent() {
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cerdito(transparent)]
+    struct FileName(String);
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cerdito(transparent)]
+    struct Id {
+        #[cerdito(skip)]
+        cached: Option<String>,
+        value: u64,
+    }
+
+    let name = FileName("a.txt".into());
+    let bytes = encode(&name);
+    assert_eq!(bytes, encode(&String::from("a.txt")));
+    assert_eq!(decode::<FileName>(&bytes).unwrap(), name);
+    assert_eq!(
+        encode(&vec![FileName("a".into())]),
+        encode(&vec![String::from("a")])
+    );
+
+    let id = Id {
+        cached: Some("7".into()),
+        value: 7,
+    };
+    let bytes = encode(&id);
+    assert_eq!(bytes, encode(&7_u64));
+    assert_eq!(
+        decode::<Id>(&bytes).unwrap(),
+        Id {
+            cached: None,
+            value: 7
+        }
+    );
+}
+
+#[test]
+fn test_enum_tags() {
+    #[repr(i8)]
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    enum Status {
+        #[cerdito(tag = 0)]
+        Unknown = -1,
+        Active = 5,
+        Stopped, // 6
+        #[cerdito(tag = 40, alias_tags = [7, 8])]
+        Failed(String) = 7,
+        #[cerdito(tag = 4294967295)]
+        Other,
+    }
+
+    assert_eq!(encode(&Status::Unknown), encode(&0_u32));
+    assert_eq!(encode(&Status::Active), encode(&5_u32));
+    assert_eq!(encode(&Status::Stopped), encode(&6_u32));
+    assert_eq!(encode(&Status::Other), encode(&u32::MAX));
+    for status in [
+        Status::Unknown,
+        Status::Active,
+        Status::Stopped,
+        Status::Failed("oops".into()),
+        Status::Other,
+    ] {
+        assert_eq!(decode::<Status>(&encode(&status)).unwrap(), status);
+    }
+
+    // legacy tags are accepted on decode, the new tag is written
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    enum OldStatus {
+        #[cerdito(tag = 7)]
+        Failed(String),
+        Crashed(String), // 8
+    }
+    let bytes = encode(&OldStatus::Crashed("oops".into()));
+    let status = decode::<Status>(&bytes).unwrap();
+    assert_eq!(status, Status::Failed("oops".into()));
+    assert_eq!(
+        decode::<OldStatus>(&encode(&status))
+            .unwrap_err()
+            .to_string(),
+        "enum OldStatus doesn't support variant 40 at byte 0 (OldStatus)"
+    );
+    assert!(matches!(
+        decode::<Status>(&encode(&7_u32)),
+        Ok(Status::Failed(s)) if s.is_empty()
+    ));
+    assert!(matches!(
+        decode::<Status>(&encode(&9_u32)).unwrap_err().root(),
+        Error::UnknownVariant { tag: 9, .. }
+    ));
+
+    // tags given by const expressions
+    const BASE: u64 = 1 << 20;
+    #[repr(u64)]
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    enum Kind {
+        A = BASE,
+        B, // BASE + 1
+        #[cerdito(tag = BASE as u32 * 2, alias_tags = [BASE + 2])]
+        C(String) = 3,
+        D = 3 + 1,
+    }
+    assert_eq!(encode(&Kind::A), encode(&(1_u32 << 20)));
+    assert_eq!(encode(&Kind::B), encode(&((1_u32 << 20) + 1)));
+    assert_eq!(encode(&Kind::D), encode(&4_u32));
+    for kind in [Kind::A, Kind::B, Kind::C("c".into()), Kind::D] {
+        assert_eq!(decode::<Kind>(&encode(&kind)).unwrap(), kind);
+    }
+    assert_eq!(
+        decode::<Kind>(&encode(&((1_u32 << 20) + 2))).unwrap(),
+        Kind::C(String::new())
+    );
+}
+
+#[test]
+fn test_renamed_error_path() {
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cerdito(rename = "msg", rename_all = "camelCase")]
+    struct Message {
+        message_id: u32,
+        #[cerdito(rename = "body", required)]
+        payload: Payload,
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cerdito(rename_all = "SCREAMING_SNAKE_CASE")]
+    enum Payload {
+        #[cerdito(rename_all = "kebab-case")]
+        HTTPRequest { line_count: u8, raw_text: String },
+        #[cerdito(rename = "txt")]
+        Text(#[cerdito(rename = "text")] String),
+    }
+
+    fn error_path(value: &Message) -> String {
+        let bytes = encode(value);
+        let bytes = &bytes[..bytes.len() - 1];
+        match Decoder::new(SliceReader::new(bytes)).decode::<Message>() {
+            Err(Error::At { path, .. }) => path,
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    let request = Payload::HTTPRequest {
+        line_count: 1,
+        raw_text: "GET".into(),
+    };
+    assert_eq!(
+        error_path(&Message {
+            message_id: 1,
+            payload: request
+        }),
+        "msg.body.<HTTP_REQUEST>.raw-text"
+    );
+    assert_eq!(
+        error_path(&Message {
+            message_id: 1,
+            payload: Payload::Text("hi".into())
+        }),
+        "msg.body.<txt>.text"
+    );
+}
+
+#[test]
+fn test_inferred_bounds() {
+    use std::marker::PhantomData;
+
+    // implements neither `Encode`, `Decode` nor `Default`
+    #[derive(Debug, PartialEq)]
+    struct Opaque;
+
+    // `Decode` needs `T: Default` for old data without the field
+    #[derive(Debug, PartialEq, Default, Encode, Decode)]
+    struct Wrapper<T>(T);
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Tagged<T, K> {
+        value: Wrapper<T>,
+        values: Vec<T>,
+        #[cerdito(skip)]
+        kind: PhantomData<K>,
+        next: Option<Box<Tagged<T, K>>>,
+    }
+
+    let value = Tagged::<u8, Opaque> {
+        value: Wrapper(1),
+        values: vec![2, 3],
+        kind: PhantomData,
+        next: Some(Box::new(Tagged {
+            value: Wrapper(4),
+            values: vec![],
+            kind: PhantomData,
+            next: None,
+        })),
+    };
+    assert_eq!(roundtrip(&value), value);
+
+    // mutually recursive types need an explicit bound on one of them
+    #[derive(Debug, PartialEq, Default, Encode, Decode)]
+    #[cerdito(bound(encode = "T: Encode", decode = "T: Decode + Default"))]
+    struct Forest<T> {
+        #[cerdito(boxed)]
+        trees: Vec<Tree<T>>,
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Tree<T> {
+        value: T,
+        forest: Forest<T>,
+    }
+
+    let value = Tree {
+        value: 1_u8,
+        forest: Forest {
+            trees: vec![Tree {
+                value: 2,
+                forest: Forest::default(),
+            }],
+        },
+    };
+    assert_eq!(roundtrip(&value), value);
+
+    // a type of the same name in another module isn't recursive and is bounded
+    mod v1 {
+        #[derive(Debug, PartialEq, Default, cerdito::Encode, cerdito::Decode)]
+        pub struct Item<T>(pub T);
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Item<T> {
+        old: v1::Item<T>,
+        next: Option<Box<Self>>,
+    }
+
+    let value = Item {
+        old: v1::Item(1_u8),
+        next: Some(Box::new(Item {
+            old: v1::Item(2),
+            next: None,
+        })),
+    };
+    assert_eq!(roundtrip(&value), value);
+}
+
+#[test]
+fn test_crate_path() {
+    // a facade crate that re-exports cerdito
+    mod facade {
+        pub use cerdito;
+    }
+    use facade::cerdito::{Decode, Encode};
+
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    #[cerdito(crate = "facade::cerdito")]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let value = Point { x: 1, y: -1 };
+    assert_eq!(encode(&value), encode(&(1_i32, -1_i32)));
+    assert_eq!(roundtrip(&value), value);
+}
+
+#[test]
+fn test_async_recursive_types() {
+    #[derive(Debug, PartialEq, Default, Encode, Decode)]
+    enum List {
+        Cons(u8, Box<List>),
+        #[default]
+        Nil,
+    }
+    // the field refers to the type itself, its futures are boxed automatically
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Tree {
+        value: u8,
+        children: Vec<Tree>,
+    }
+    // `Self` refers to the type itself too
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Branch {
+        value: u8,
+        branches: Vec<Self>,
+    }
+    // mutually recursive types need `boxed` on one of the fields
+    #[derive(Debug, PartialEq, Default, Encode, Decode)]
+    struct Forest {
+        #[cerdito(boxed)]
+        trees: Vec<Node>,
+    }
+    #[derive(Debug, PartialEq, Encode, Decode)]
+    struct Node {
+        value: u8,
+        forest: Forest,
+    }
+
+    let list = || List::Cons(1, Box::new(List::Cons(2, Box::new(List::Nil))));
+    assert_eq!(roundtrip(&list()), list());
+    let tree = || Tree {
+        value: 1,
+        children: vec![
+            Tree {
+                value: 2,
+                children: vec![],
+            },
+            Tree {
+                value: 3,
+                children: vec![Tree {
+                    value: 4,
+                    children: vec![],
+                }],
+            },
+        ],
+    };
+    assert_eq!(roundtrip(&tree()), tree());
+    let branch = || Branch {
+        value: 1,
+        branches: vec![Branch {
+            value: 2,
+            branches: vec![],
+        }],
+    };
+    assert_eq!(roundtrip(&branch()), branch());
+    let node = || Node {
+        value: 1,
+        forest: Forest {
+            trees: vec![Node {
+                value: 2,
+                forest: Forest::default(),
+            }],
+        },
+    };
+    assert_eq!(roundtrip(&node()), node());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_futures() {
+    use cerdito::{SendDecode, SendEncode};
+
+    #[derive(Debug, PartialEq, Encode, Decode, SendEncode, SendDecode)]
+    struct Pair<T> {
+        key: String,
+        value: T,
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode, SendEncode, SendDecode)]
+    enum Tree {
+        Leaf(u8),
+        Node(Vec<Tree>),
+    }
+
+    #[derive(Debug, PartialEq, Encode, Decode, SendEncode, SendDecode)]
+    enum Hash {
+        Fixed(#[cerdito(with = fixed_bytes)] u64),
+    }
+
+    // generic code can spawn the futures on a multi-threaded runtime
+    async fn roundtrip_send<T: SendEncode + SendDecode + Send + 'static>(value: T) -> T {
+        let bytes = encode(&value);
+        let task = tokio::spawn(async move {
+            let mut encoder = Encoder::new(Vec::new());
+            value.encode_send(&mut encoder).await.unwrap();
+            encoder.writer
+        });
+        assert_eq!(task.await.unwrap(), bytes);
+        let task = tokio::spawn(async move {
+            let mut decoder = Decoder::new(std::io::Cursor::new(bytes));
+            T::decode_send(&mut decoder).await.unwrap()
+        });
+        task.await.unwrap()
+    }
+
+    let pair = || Pair {
+        key: String::from("a"),
+        value: vec![1_u32, 2, 3],
+    };
+    assert_eq!(roundtrip_send(pair()).await, pair());
+    let tree = || Tree::Node(vec![Tree::Leaf(1), Tree::Node(vec![Tree::Leaf(2)])]);
+    assert_eq!(roundtrip_send(tree()).await, tree());
+    assert_eq!(roundtrip_send(Hash::Fixed(7)).await, Hash::Fixed(7));
+}
@@ -0,0 +1,17 @@
+use cerdito::Encode;
+
+#[derive(Encode)]
+enum E {
+    A = 1,
+    #[cerdito(tag = 1)]
+    B,
+}
+
+#[derive(Encode)]
+enum F {
+    A,
+    #[cerdito(alias_tags = [0])]
+    B,
+}
+
+fn main() {}
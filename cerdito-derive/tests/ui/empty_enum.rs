@@ -0,0 +1,9 @@
+use cerdito::{Decode, Encode};
+
+#[derive(Encode)]
+enum Never {}
+
+#[derive(Decode)]
+enum Void {}
+
+fn main() {}
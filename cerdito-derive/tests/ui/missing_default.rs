@@ -0,0 +1,11 @@
+use cerdito::Decode;
+
+struct NoDefault;
+
+#[derive(Decode)]
+struct S {
+    #[cerdito(skip)]
+    a: NoDefault,
+}
+
+fn main() {}
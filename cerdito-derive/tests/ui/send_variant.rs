@@ -0,0 +1,12 @@
+use cerdito_derive::send_variant;
+
+#[send_variant]
+struct S;
+
+#[send_variant(Self Send)]
+trait A {}
+
+#[send_variant(trait_method)]
+fn f() {}
+
+fn main() {}
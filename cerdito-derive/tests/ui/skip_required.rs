@@ -0,0 +1,9 @@
+use cerdito::Decode;
+
+#[derive(Decode)]
+struct S {
+    #[cerdito(skip, required)]
+    a: u32,
+}
+
+fn main() {}
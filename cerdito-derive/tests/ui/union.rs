@@ -0,0 +1,9 @@
+use cerdito::Encode;
+
+#[derive(Encode)]
+union U {
+    a: u32,
+    b: f32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use cerdito::Encode;
+
+#[derive(Encode)]
+struct S {
+    #[cerdito(skip_all)]
+    a: u32,
+}
+
+fn main() {}
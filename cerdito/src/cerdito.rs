@@ -1,14 +1,51 @@
 #![allow(async_fn_in_trait)]
 
 use build_async::*;
+use cerdito_derive::{_await_send, send_variant};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
 use std::convert::TryInto;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
+use std::hash::{BuildHasher, Hash};
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+use std::sync::Arc;
+
+//------Error----------------------------
+
+pub trait Error: Sized {
+    /// Creates an error from an arbitrary message.
+    fn custom<T: Display>(msg: T) -> Self;
+
+    /// Enum tag read from the input does not match any known variant.
+    fn unknown_variant(enum_name: &str, tag: u32) -> Self {
+        Self::custom(format_args!(
+            "enum {} doesn't support variant {}",
+            enum_name, tag
+        ))
+    }
+
+    /// Sequence, array or byte string has an unexpected length.
+    fn invalid_length(expected: usize, got: usize) -> Self {
+        Self::custom(format_args!(
+            "invalid length {}, expected {}",
+            got, expected
+        ))
+    }
+
+    /// Required struct field is not present in the input.
+    fn missing_field(name: &str) -> Self {
+        Self::custom(format_args!("missing field {}", name))
+    }
+}
 
 //------Decoder----------------------------
 
 macro_rules! fn_decode_arr {
-    ($ty:ty) => {
+    ($(#[$attr:meta])* $ty:ty) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<decode_arr_ $ty>](&mut self, len: Option<usize>) -> Result<Vec<$ty>, Self::Error> {
                 _await!(self.[<decode_vec_ $ty>](len))
             }
@@ -16,8 +53,16 @@ macro_rules! fn_decode_arr {
     };
 }
 
+#[send_variant(Self: Send)]
 pub trait Decoder {
-    type Error;
+    type Error: Error;
+
+    /// Adds the context of the decoder, e.g. the input position, to an error raised by a
+    /// `Decode` implementation. Implementations should pass the errors they create through
+    /// it. Returns the error as is by default.
+    fn annotate(&self, error: Self::Error) -> Self::Error {
+        error
+    }
 
     // scalars
     #[_async]
@@ -45,6 +90,26 @@ pub trait Decoder {
     #[_async]
     fn decode_i128(&mut self) -> Result<i128, Self::Error>;
     #[_async]
+    fn decode_usize(&mut self) -> Result<usize, Self::Error> {
+        let v = _await!(self.decode_u64())?;
+        v.try_into().map_err(|_| {
+            self.annotate(Self::Error::custom(format_args!(
+                "{} doesn't fit into usize",
+                v
+            )))
+        })
+    }
+    #[_async]
+    fn decode_isize(&mut self) -> Result<isize, Self::Error> {
+        let v = _await!(self.decode_i64())?;
+        v.try_into().map_err(|_| {
+            self.annotate(Self::Error::custom(format_args!(
+                "{} doesn't fit into isize",
+                v
+            )))
+        })
+    }
+    #[_async]
     fn decode_f32(&mut self) -> Result<f32, Self::Error>;
     #[_async]
     fn decode_f64(&mut self) -> Result<f64, Self::Error>;
@@ -118,6 +183,16 @@ pub trait Decoder {
         _await!(self.decode_seq_end())
     }
 
+    // maps, i.e. sequences of (key, value) pairs
+    #[_async]
+    fn decode_map_begin(&mut self) -> Result<usize, Self::Error> {
+        _await!(self.decode_seq_begin(None))
+    }
+    #[_async]
+    fn decode_map_end(&mut self) -> Result<(), Self::Error> {
+        _await!(self.decode_seq_end())
+    }
+
     // sequences
     #[_async]
     fn decode_seq_begin(&mut self, len: Option<usize>) -> Result<usize, Self::Error>;
@@ -168,8 +243,9 @@ pub trait Decoder {
 
 //--------Encoder--------------------
 macro_rules! fn_encode_arr {
-    ($ty:ty) => {
+    ($(#[$attr:meta])* $ty:ty) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<encode_arr_ $ty>](&mut self, values: &[$ty]) -> Result<(), Self::Error> {
                 _await!(self.[<encode_vec_ $ty>](values))
             }
@@ -177,8 +253,9 @@ macro_rules! fn_encode_arr {
     };
 }
 
+#[send_variant(Self: Send)]
 pub trait Encoder {
-    type Error;
+    type Error: Error;
 
     // scalars
     #[_async]
@@ -206,6 +283,20 @@ pub trait Encoder {
     #[_async]
     fn encode_i128(&mut self, value: &i128) -> Result<(), Self::Error>;
     #[_async]
+    fn encode_usize(&mut self, value: &usize) -> Result<(), Self::Error> {
+        let v: u64 = (*value)
+            .try_into()
+            .map_err(|_| Self::Error::custom(format_args!("{} doesn't fit into u64", value)))?;
+        _await!(self.encode_u64(&v))
+    }
+    #[_async]
+    fn encode_isize(&mut self, value: &isize) -> Result<(), Self::Error> {
+        let v: i64 = (*value)
+            .try_into()
+            .map_err(|_| Self::Error::custom(format_args!("{} doesn't fit into i64", value)))?;
+        _await!(self.encode_i64(&v))
+    }
+    #[_async]
     fn encode_f32(&mut self, value: &f32) -> Result<(), Self::Error>;
     #[_async]
     fn encode_f64(&mut self, value: &f64) -> Result<(), Self::Error>;
@@ -280,6 +371,16 @@ pub trait Encoder {
         _await!(self.encode_seq_end())
     }
 
+    // maps, i.e. sequences of (key, value) pairs
+    #[_async]
+    fn encode_map_begin(&mut self, len: usize) -> Result<(), Self::Error> {
+        _await!(self.encode_seq_begin(len))
+    }
+    #[_async]
+    fn encode_map_end(&mut self) -> Result<(), Self::Error> {
+        _await!(self.encode_seq_end())
+    }
+
     // sequences
     #[_async]
     fn encode_seq_begin(&mut self, len: usize) -> Result<(), Self::Error>;
@@ -334,6 +435,14 @@ pub trait Encoder {
 
 //-------Decode-----------------------
 
+/// Limits preallocation for lengths read from the input, so that a bogus length
+/// can't reserve more than 1 MiB before any element is decoded.
+fn cautious_capacity<T>(len: usize) -> usize {
+    const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+    len.min(MAX_PREALLOC_BYTES / std::mem::size_of::<T>().max(1))
+}
+
+#[send_variant(Self: Send)]
 pub trait Decode {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error>
@@ -344,9 +453,10 @@ pub trait Decode {
 macro_rules! impl_decode {
     ($ty:ty) => {
         paste::item! {
+            #[send_variant]
             impl Decode for $ty {
                 #[_async] fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-                    Ok(_await!(decoder.[<decode_ $ty>]())?)
+                    _await!(decoder.[<decode_ $ty>]())
                 }
             }
         }
@@ -360,29 +470,82 @@ impl_decode! {u16}
 impl_decode! {u32}
 impl_decode! {u64}
 impl_decode! {u128}
+impl_decode! {usize}
 impl_decode! {i8}
 impl_decode! {i16}
 impl_decode! {i32}
 impl_decode! {i64}
 impl_decode! {i128}
+impl_decode! {isize}
 impl_decode! {f32}
 impl_decode! {f64}
 
+#[send_variant]
 impl Decode for String {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        Ok(_await!(decoder.decode_string())?)
+        _await!(decoder.decode_string())
     }
 }
 
+#[send_variant]
 impl<T: Decode> Decode for Box<T> {
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(Box::new(T::decode(decoder)?))
+    }
+    // the future is boxed, so that recursive types like `enum S { S1(Box<S>), S2 }` can be
+    // decoded asynchronously
+    async fn decode_async<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(Box::new(Box::pin(T::decode_async(decoder)).await?))
+    }
+}
+
+#[send_variant]
+impl Decode for Box<str> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(_await!(String::decode(decoder))?.into_boxed_str())
+    }
+}
+
+#[send_variant]
+impl<T: Decode> Decode for Box<[T]> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(_await!(Vec::<T>::decode(decoder))?.into_boxed_slice())
+    }
+}
+
+impl<T: Decode> Decode for Rc<T> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(Rc::new(_await!(T::decode(decoder))?))
+    }
+}
+
+#[send_variant(T: Sync)]
+impl<T: Decode> Decode for Arc<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        Ok(Box::new(_await!(T::decode(decoder))?))
+        Ok(Arc::new(_await!(T::decode(decoder))?))
     }
 }
 
-impl<T: Default + Decode> Decode for Option<T> {
+#[send_variant(T: Sync)]
+impl<T: ToOwned + ?Sized> Decode for Cow<'_, T>
+where
+    T::Owned: Decode,
+{
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        Ok(Cow::Owned(_await!(T::Owned::decode(decoder))?))
+    }
+}
+
+/// `Some` must carry its payload: `T` isn't required to implement `Default`, so `Some` without
+/// one fails with `invalid_length` instead of decoding as `Some(T::default())`.
+#[send_variant]
+impl<T: Decode> Decode for Option<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
         let (tag, enum_len) = _await!(decoder.decode_enum_begin("Option"))?;
@@ -395,18 +558,16 @@ impl<T: Default + Decode> Decode for Option<T> {
                     _await!(decoder.decode_struct_end())?;
                     Self::None
                 }
-                _ => unreachable!(),
+                _ => return Err(decoder.annotate(D::Error::invalid_length(1, enum_len))),
             },
             1 => match enum_len {
-                0 => Self::Some(T::default()),
                 1 => {
                     let len = _await!(decoder.decode_struct_begin(1, None))?;
+                    if len < 1 {
+                        return Err(decoder.annotate(D::Error::invalid_length(1, len)));
+                    }
                     _await!(decoder.decode_elem_begin(0, None))?;
-                    let field_0 = if len > 0 {
-                        _await!(<T as Decode>::decode(decoder))?
-                    } else {
-                        <T>::default()
-                    };
+                    let field_0 = _await!(<T as Decode>::decode(decoder))?;
                     _await!(decoder.decode_elem_end())?;
                     if len > 1 {
                         _await!(decoder.decode_skip(len - 1))?;
@@ -414,15 +575,16 @@ impl<T: Default + Decode> Decode for Option<T> {
                     _await!(decoder.decode_struct_end())?;
                     Self::Some(field_0)
                 }
-                _ => unreachable!(),
+                _ => return Err(decoder.annotate(D::Error::invalid_length(1, enum_len))),
             },
-            _ => panic!("Enum Option doesn't support variant {}", tag),
+            _ => return Err(decoder.annotate(D::Error::unknown_variant("Option", tag))),
         };
         _await!(decoder.decode_enum_end())?;
         Ok(v)
     }
 }
 
+#[send_variant]
 impl Decode for ByteVec {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
@@ -430,19 +592,24 @@ impl Decode for ByteVec {
     }
 }
 
+#[send_variant]
 impl<const N: usize> Decode for ByteArr<N> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
         let value = _await!(decoder.decode_binary(Some(N)))?;
-        Ok(ByteArr(value.try_into().unwrap()))
+        let len = value.len();
+        Ok(ByteArr(value.try_into().map_err(|_| {
+            decoder.annotate(D::Error::invalid_length(N, len))
+        })?))
     }
 }
 
+#[send_variant]
 impl<T: Decode> Decode for Vec<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
         let len = _await!(decoder.decode_seq_begin(None))?;
-        let mut value = Vec::with_capacity(len);
+        let mut value = Vec::with_capacity(cautious_capacity::<T>(len));
         for i in 0..len {
             _await!(decoder.decode_elem_begin(i, None))?;
             value.push(_await!(T::decode(decoder))?);
@@ -453,81 +620,233 @@ impl<T: Decode> Decode for Vec<T> {
     }
 }
 
-impl<T: Decode + Debug, const N: usize> Decode for [T; N] {
+/// Array under construction; drops the elements pushed so far if it isn't finished.
+struct ArrayBuilder<T, const N: usize> {
+    data: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayBuilder<T, N> {
+    fn new() -> Self {
+        Self {
+            data: std::array::from_fn(|_| MaybeUninit::uninit()),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: T) {
+        self.data[self.len].write(value);
+        self.len += 1;
+    }
+
+    fn finish(self) -> [T; N] {
+        assert_eq!(self.len, N, "array is not fully initialized");
+        let this = ManuallyDrop::new(self);
+        // SAFETY: all `N` elements are initialized, and `this` won't drop them.
+        unsafe { std::ptr::read(this.data.as_ptr() as *const [T; N]) }
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayBuilder<T, N> {
+    fn drop(&mut self) {
+        for elem in &mut self.data[..self.len] {
+            // SAFETY: the first `len` elements are initialized.
+            unsafe { elem.assume_init_drop() }
+        }
+    }
+}
+
+#[send_variant]
+impl<T: Decode, const N: usize> Decode for [T; N] {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
         let len = _await!(decoder.decode_seq_begin(Some(N)))?;
-        let mut value = Vec::with_capacity(len);
-        for i in 0..len {
+        if len != N {
+            return Err(decoder.annotate(D::Error::invalid_length(N, len)));
+        }
+        let mut value = ArrayBuilder::<T, N>::new();
+        for i in 0..N {
             _await!(decoder.decode_elem_begin(i, None))?;
             value.push(_await!(T::decode(decoder))?);
             _await!(decoder.decode_elem_end())?;
         }
         _await!(decoder.decode_seq_end())?;
-        Ok(value.try_into().unwrap())
+        Ok(value.finish())
     }
 }
 
-impl Decode for () {
+#[send_variant(C: Send)]
+#[_async]
+fn decode_seq_elems<D: Decoder, T: Decode, C: Extend<T>>(
+    decoder: &mut D,
+    len: usize,
+    value: &mut C,
+) -> Result<(), D::Error> {
+    for i in 0..len {
+        _await!(decoder.decode_elem_begin(i, None))?;
+        value.extend(Some(_await!(T::decode(decoder))?));
+        _await!(decoder.decode_elem_end())?;
+    }
+    Ok(())
+}
+
+#[send_variant(C: Send)]
+#[_async]
+fn decode_map_entries<D: Decoder, K: Decode, V: Decode, C: Extend<(K, V)>>(
+    decoder: &mut D,
+    len: usize,
+    value: &mut C,
+) -> Result<(), D::Error> {
+    for i in 0..len {
+        _await!(decoder.decode_elem_begin(i, None))?;
+        let entry_len = _await!(decoder.decode_struct_begin(2, None))?;
+        if entry_len < 2 {
+            return Err(decoder.annotate(D::Error::invalid_length(2, entry_len)));
+        }
+        _await!(decoder.decode_elem_begin(0, None))?;
+        let k = _await!(K::decode(decoder))?;
+        _await!(decoder.decode_elem_end())?;
+        _await!(decoder.decode_elem_begin(1, None))?;
+        let v = _await!(V::decode(decoder))?;
+        _await!(decoder.decode_elem_end())?;
+        if entry_len > 2 {
+            _await!(decoder.decode_skip(entry_len - 2))?;
+        }
+        _await!(decoder.decode_struct_end())?;
+        _await!(decoder.decode_elem_end())?;
+        value.extend(Some((k, v)));
+    }
+    Ok(())
+}
+
+#[send_variant]
+impl<T: Decode> Decode for VecDeque<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        let len = _await!(decoder.decode_struct_begin(0, None))?;
-        _await!(decoder.decode_skip(len))?;
-        _await!(decoder.decode_struct_end())?;
-        Ok(())
+        let len = _await!(decoder.decode_seq_begin(None))?;
+        let mut value = VecDeque::with_capacity(cautious_capacity::<T>(len));
+        _await!(decode_seq_elems(decoder, len, &mut value))?;
+        _await!(decoder.decode_seq_end())?;
+        Ok(value)
     }
 }
 
-impl<T0: Decode + Default> Decode for (T0,) {
+#[send_variant]
+impl<T: Decode> Decode for LinkedList<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        let len = _await!(decoder.decode_struct_begin(1, None))?;
-        let v0 = if len > 0 {
-            _await!(decoder.decode_elem_begin(0, None))?;
-            let v0 = _await!(T0::decode(decoder))?;
-            _await!(decoder.decode_elem_end())?;
-            v0
-        } else {
-            T0::default()
-        };
-        if len > 1 {
-            _await!(decoder.decode_skip(len - 1))?;
-        }
-        _await!(decoder.decode_struct_end())?;
-        Ok((v0,))
+        let len = _await!(decoder.decode_seq_begin(None))?;
+        let mut value = LinkedList::new();
+        _await!(decode_seq_elems(decoder, len, &mut value))?;
+        _await!(decoder.decode_seq_end())?;
+        Ok(value)
     }
 }
 
-impl<T0: Decode + Default, T1: Decode + Default> Decode for (T0, T1) {
+#[send_variant]
+impl<T: Decode + Ord> Decode for BinaryHeap<T> {
     #[_async]
     fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        let len = _await!(decoder.decode_struct_begin(2, None))?;
-        let v0 = if len > 0 {
-            _await!(decoder.decode_elem_begin(0, None))?;
-            let v0 = _await!(T0::decode(decoder))?;
-            _await!(decoder.decode_elem_end())?;
-            v0
-        } else {
-            T0::default()
-        };
-        let v1 = if len > 1 {
-            _await!(decoder.decode_elem_begin(1, None))?;
-            let v1 = _await!(T1::decode(decoder))?;
-            _await!(decoder.decode_elem_end())?;
-            v1
-        } else {
-            T1::default()
-        };
-        if len > 2 {
-            _await!(decoder.decode_skip(len - 2))?;
-        }
+        let len = _await!(decoder.decode_seq_begin(None))?;
+        let mut value = BinaryHeap::with_capacity(cautious_capacity::<T>(len));
+        _await!(decode_seq_elems(decoder, len, &mut value))?;
+        _await!(decoder.decode_seq_end())?;
+        Ok(value)
+    }
+}
+
+#[send_variant(S: Send)]
+impl<T: Decode + Eq + Hash, S: BuildHasher + Default> Decode for HashSet<T, S> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        let len = _await!(decoder.decode_seq_begin(None))?;
+        let mut value =
+            HashSet::with_capacity_and_hasher(cautious_capacity::<T>(len), S::default());
+        _await!(decode_seq_elems(decoder, len, &mut value))?;
+        _await!(decoder.decode_seq_end())?;
+        Ok(value)
+    }
+}
+
+#[send_variant]
+impl<T: Decode + Ord> Decode for BTreeSet<T> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        let len = _await!(decoder.decode_seq_begin(None))?;
+        let mut value = BTreeSet::new();
+        _await!(decode_seq_elems(decoder, len, &mut value))?;
+        _await!(decoder.decode_seq_end())?;
+        Ok(value)
+    }
+}
+
+#[send_variant(S: Send)]
+impl<K: Decode + Eq + Hash, V: Decode, S: BuildHasher + Default> Decode for HashMap<K, V, S> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        let len = _await!(decoder.decode_map_begin())?;
+        let mut value =
+            HashMap::with_capacity_and_hasher(cautious_capacity::<(K, V)>(len), S::default());
+        _await!(decode_map_entries(decoder, len, &mut value))?;
+        _await!(decoder.decode_map_end())?;
+        Ok(value)
+    }
+}
+
+#[send_variant]
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        let len = _await!(decoder.decode_map_begin())?;
+        let mut value = BTreeMap::new();
+        _await!(decode_map_entries(decoder, len, &mut value))?;
+        _await!(decoder.decode_map_end())?;
+        Ok(value)
+    }
+}
+
+#[send_variant]
+impl Decode for () {
+    #[_async]
+    fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+        let len = _await!(decoder.decode_struct_begin(0, None))?;
+        _await!(decoder.decode_skip(len))?;
         _await!(decoder.decode_struct_end())?;
-        Ok((v0, v1))
+        Ok(())
     }
 }
 
+macro_rules! impl_decode_tuple {
+    ($($idx:tt $t:ident $v:ident)+) => {
+        #[send_variant]
+        impl<$($t: Decode + Default),+> Decode for ($($t,)+) {
+            #[_async]
+            fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                const LEN: usize = [$($idx),+].len();
+                let len = _await!(decoder.decode_struct_begin(LEN, None))?;
+                $(
+                    let $v = if len > $idx {
+                        _await!(decoder.decode_elem_begin($idx, None))?;
+                        let $v = _await!($t::decode(decoder))?;
+                        _await!(decoder.decode_elem_end())?;
+                        $v
+                    } else {
+                        $t::default()
+                    };
+                )+
+                if len > LEN {
+                    _await!(decoder.decode_skip(len - LEN))?;
+                }
+                _await!(decoder.decode_struct_end())?;
+                Ok(($($v,)+))
+            }
+        }
+    };
+}
+
 //------Encode--------------------
 
+#[send_variant(Self: Sync)]
 pub trait Encode {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error>;
@@ -536,6 +855,7 @@ pub trait Encode {
 macro_rules! impl_encode {
     ($ty:ty) => {
         paste::item! {
+            #[send_variant]
             impl Encode for $ty {
                 #[_async] fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
                     _await!(encoder.[<encode_ $ty>](self))?;
@@ -553,14 +873,17 @@ impl_encode! {u16}
 impl_encode! {u32}
 impl_encode! {u64}
 impl_encode! {u128}
+impl_encode! {usize}
 impl_encode! {i8}
 impl_encode! {i16}
 impl_encode! {i32}
 impl_encode! {i64}
 impl_encode! {i128}
+impl_encode! {isize}
 impl_encode! {f32}
 impl_encode! {f64}
 
+#[send_variant]
 impl Encode for String {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -569,6 +892,7 @@ impl Encode for String {
     }
 }
 
+#[send_variant]
 impl<T: Encode> Encode for Option<T> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -580,7 +904,7 @@ impl<T: Encode> Encode for Option<T> {
             Self::Some(v) => {
                 _await!(encoder.encode_enum_begin(1, 1, "Option", "Some"))?;
                 _await!(encoder.encode_struct_begin(1, None))?;
-                _await!(encoder.encode_elem_begin(1, None))?;
+                _await!(encoder.encode_elem_begin(0, None))?;
                 _await!(v.encode(encoder))?;
                 _await!(encoder.encode_elem_end())?;
                 _await!(encoder.encode_struct_end())?;
@@ -591,23 +915,76 @@ impl<T: Encode> Encode for Option<T> {
     }
 }
 
-impl<T: Encode> Encode for Box<T> {
+#[send_variant]
+impl Encode for str {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(T::encode(self, encoder))?;
-        Ok(())
+        _await!(encoder.encode_string(self))
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct ByteVec(pub Vec<u8>);
+#[send_variant]
+impl<T: Encode> Encode for [T] {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
+    }
+}
 
-impl Default for ByteVec {
-    fn default() -> Self {
-        ByteVec(Vec::new())
+#[send_variant]
+impl<T: Encode + ?Sized> Encode for &T {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(T::encode(self, encoder))
+    }
+}
+
+#[send_variant]
+impl<T: Encode + ?Sized> Encode for &mut T {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(T::encode(self, encoder))
     }
 }
 
+#[send_variant]
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        T::encode(self, encoder)
+    }
+    // the future is boxed, see `Decode for Box<T>`
+    async fn encode_async<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        Box::pin(T::encode_async(self, encoder)).await
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Rc<T> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(T::encode(self, encoder))
+    }
+}
+
+#[send_variant(T: Send)]
+impl<T: Encode + ?Sized> Encode for Arc<T> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(T::encode(self, encoder))
+    }
+}
+
+#[send_variant(T::Owned: Sync)]
+impl<T: Encode + ToOwned + ?Sized> Encode for Cow<'_, T> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(T::encode(self, encoder))
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct ByteVec(pub Vec<u8>);
+
+#[send_variant]
 impl Encode for ByteVec {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -626,6 +1003,7 @@ impl<const N: usize> Default for ByteArr<N> {
     }
 }
 
+#[send_variant]
 impl<const N: usize> Encode for ByteArr<N> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -635,6 +1013,129 @@ impl<const N: usize> Encode for ByteArr<N> {
     }
 }
 
+/// Fixed size array of primitives, encoded with the packed `encode_arr_*`/`decode_arr_*` methods.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Arr<T, const N: usize>(pub [T; N]);
+
+impl<T: Default, const N: usize> Default for Arr<T, N> {
+    fn default() -> Self {
+        Arr(std::array::from_fn(|_| T::default()))
+    }
+}
+
+impl<T, const N: usize> Deref for Arr<T, N> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T, const N: usize> DerefMut for Arr<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+impl<T, const N: usize> From<[T; N]> for Arr<T, N> {
+    fn from(value: [T; N]) -> Self {
+        Arr(value)
+    }
+}
+
+impl<T, const N: usize> From<Arr<T, N>> for [T; N] {
+    fn from(value: Arr<T, N>) -> Self {
+        value.0
+    }
+}
+
+/// Variable size array of primitives, encoded with the packed `encode_vec_*`/`decode_vec_*` methods.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct VArr<T>(pub Vec<T>);
+
+impl<T> Deref for VArr<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for VArr<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+impl<T> From<Vec<T>> for VArr<T> {
+    fn from(value: Vec<T>) -> Self {
+        VArr(value)
+    }
+}
+
+impl<T> From<VArr<T>> for Vec<T> {
+    fn from(value: VArr<T>) -> Self {
+        value.0
+    }
+}
+
+impl<T: Clone> From<&[T]> for VArr<T> {
+    fn from(value: &[T]) -> Self {
+        VArr(value.to_vec())
+    }
+}
+
+macro_rules! impl_packed {
+    ($ty:ty) => {
+        paste::item! {
+            #[send_variant]
+            impl<const N: usize> Encode for Arr<$ty, N> {
+                #[_async] fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+                    _await!(encoder.[<encode_arr_ $ty>](&self.0))
+                }
+            }
+            #[send_variant]
+            impl<const N: usize> Decode for Arr<$ty, N> {
+                #[_async] fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                    let value = _await!(decoder.[<decode_arr_ $ty>](Some(N)))?;
+                    let len = value.len();
+                    Ok(Arr(
+                        value
+                            .try_into()
+                            .map_err(|_| decoder.annotate(D::Error::invalid_length(N, len)))?,
+                    ))
+                }
+            }
+            #[send_variant]
+            impl Encode for VArr<$ty> {
+                #[_async] fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+                    _await!(encoder.[<encode_vec_ $ty>](&self.0))
+                }
+            }
+            #[send_variant]
+            impl Decode for VArr<$ty> {
+                #[_async] fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
+                    Ok(VArr(_await!(decoder.[<decode_vec_ $ty>](None))?))
+                }
+            }
+        }
+    };
+}
+
+impl_packed! {bool}
+impl_packed! {char}
+impl_packed! {u8}
+impl_packed! {u16}
+impl_packed! {u32}
+impl_packed! {u64}
+impl_packed! {u128}
+impl_packed! {i8}
+impl_packed! {i16}
+impl_packed! {i32}
+impl_packed! {i64}
+impl_packed! {i128}
+impl_packed! {f32}
+impl_packed! {f64}
+
+#[send_variant]
 impl<T: Encode> Encode for Vec<T> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -649,6 +1150,7 @@ impl<T: Encode> Encode for Vec<T> {
     }
 }
 
+#[send_variant]
 impl<T: Encode, const N: usize> Encode for [T; N] {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
@@ -663,40 +1165,151 @@ impl<T: Encode, const N: usize> Encode for [T; N] {
     }
 }
 
-impl Encode for () {
+#[send_variant(I: Send)]
+#[_async]
+fn encode_seq_elems<'a, E: Encoder, T: Encode + 'a, I: Iterator<Item = &'a T>>(
+    encoder: &mut E,
+    len: usize,
+    values: I,
+) -> Result<(), E::Error> {
+    _await!(encoder.encode_seq_begin(len))?;
+    for (i, v) in values.enumerate() {
+        _await!(encoder.encode_elem_begin(i, None))?;
+        _await!(v.encode(encoder))?;
+        _await!(encoder.encode_elem_end())?;
+    }
+    _await!(encoder.encode_seq_end())
+}
+
+#[send_variant(I: Send)]
+#[_async]
+fn encode_map_entries<'a, E, K, V, I>(
+    encoder: &mut E,
+    len: usize,
+    entries: I,
+) -> Result<(), E::Error>
+where
+    E: Encoder,
+    K: Encode + 'a,
+    V: Encode + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+{
+    _await!(encoder.encode_map_begin(len))?;
+    for (i, (k, v)) in entries.enumerate() {
+        _await!(encoder.encode_elem_begin(i, None))?;
+        _await!(encoder.encode_struct_begin(2, None))?;
+        _await!(encoder.encode_elem_begin(0, None))?;
+        _await!(k.encode(encoder))?;
+        _await!(encoder.encode_elem_end())?;
+        _await!(encoder.encode_elem_begin(1, None))?;
+        _await!(v.encode(encoder))?;
+        _await!(encoder.encode_elem_end())?;
+        _await!(encoder.encode_struct_end())?;
+        _await!(encoder.encode_elem_end())?;
+    }
+    _await!(encoder.encode_map_end())
+}
+
+#[send_variant]
+impl<T: Encode> Encode for VecDeque<T> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(encoder.encode_struct_begin(0, None))?;
-        _await!(encoder.encode_struct_end())?;
-        Ok(())
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
     }
 }
 
-impl<T0: Encode> Encode for (T0,) {
+#[send_variant]
+impl<T: Encode> Encode for LinkedList<T> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(encoder.encode_struct_begin(1, None))?;
-        let (v0,) = self;
-        _await!(encoder.encode_elem_begin(0, None))?;
-        _await!(v0.encode(encoder))?;
-        _await!(encoder.encode_elem_end())?;
-        _await!(encoder.encode_struct_end())?;
-        Ok(())
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
     }
 }
 
-impl<T0: Encode, T1: Encode> Encode for (T0, T1) {
+#[send_variant]
+impl<T: Encode> Encode for BinaryHeap<T> {
     #[_async]
     fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(encoder.encode_struct_begin(2, None))?;
-        let (v0, v1) = self;
-        _await!(encoder.encode_elem_begin(0, None))?;
-        _await!(v0.encode(encoder))?;
-        _await!(encoder.encode_elem_end())?;
-        _await!(encoder.encode_elem_begin(1, None))?;
-        _await!(v1.encode(encoder))?;
-        _await!(encoder.encode_elem_end())?;
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
+    }
+}
+
+#[send_variant(S: Sync)]
+impl<T: Encode, S> Encode for HashSet<T, S> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
+    }
+}
+
+#[send_variant]
+impl<T: Encode> Encode for BTreeSet<T> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encode_seq_elems(encoder, self.len(), self.iter()))
+    }
+}
+
+#[send_variant(S: Sync)]
+impl<K: Encode, V: Encode, S> Encode for HashMap<K, V, S> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encode_map_entries(encoder, self.len(), self.iter()))
+    }
+}
+
+#[send_variant]
+impl<K: Encode, V: Encode> Encode for BTreeMap<K, V> {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encode_map_entries(encoder, self.len(), self.iter()))
+    }
+}
+
+#[send_variant]
+impl Encode for () {
+    #[_async]
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+        _await!(encoder.encode_struct_begin(0, None))?;
         _await!(encoder.encode_struct_end())?;
         Ok(())
     }
 }
+
+macro_rules! impl_encode_tuple {
+    ($($idx:tt $t:ident $v:ident)+) => {
+        #[send_variant]
+        impl<$($t: Encode),+> Encode for ($($t,)+) {
+            #[_async]
+            fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
+                const LEN: usize = [$($idx),+].len();
+                _await!(encoder.encode_struct_begin(LEN, None))?;
+                let ($($v,)+) = self;
+                $(
+                    _await!(encoder.encode_elem_begin($idx, None))?;
+                    _await!($v.encode(encoder))?;
+                    _await!(encoder.encode_elem_end())?;
+                )+
+                _await!(encoder.encode_struct_end())?;
+                Ok(())
+            }
+        }
+    };
+}
+
+// Implements `Encode` and `Decode` for every prefix of the list, i.e. tuples of arity 1 to 32.
+macro_rules! impl_tuples {
+    ([$($done:tt)*]) => {};
+    ([$($done:tt)*] $idx:tt $t:ident $v:ident $($rest:tt)*) => {
+        impl_encode_tuple! {$($done)* $idx $t $v}
+        impl_decode_tuple! {$($done)* $idx $t $v}
+        impl_tuples! {[$($done)* $idx $t $v] $($rest)*}
+    };
+}
+
+impl_tuples! {[]
+    0 T0 v0 1 T1 v1 2 T2 v2 3 T3 v3 4 T4 v4 5 T5 v5 6 T6 v6 7 T7 v7
+    8 T8 v8 9 T9 v9 10 T10 v10 11 T11 v11 12 T12 v12 13 T13 v13 14 T14 v14 15 T15 v15
+    16 T16 v16 17 T17 v17 18 T18 v18 19 T19 v19 20 T20 v20 21 T21 v21 22 T22 v22 23 T23 v23
+    24 T24 v24 25 T25 v25 26 T26 v26 27 T27 v27 28 T28 v28 29 T29 v29 30 T30 v30 31 T31 v31
+}
@@ -1,3 +1,81 @@
 mod cerdito;
-pub use crate::cerdito::{ByteArr, ByteVec, Decode, Decoder, Encode, Encoder};
-pub use cerdito_derive::{Decode, Encode};
+pub use crate::cerdito::{
+    Arr, ByteArr, ByteVec, Decode, Decoder, Encode, Encoder, Error, SendDecode, SendDecoder,
+    SendEncode, SendEncoder, VArr,
+};
+pub use cerdito_derive::{Decode, Encode, SendDecode, SendEncode};
+
+/// Items used by the code generated by `cerdito-derive`, not public API.
+#[doc(hidden)]
+pub mod __private {
+    use crate::{
+        Decode, Decoder, Encode, Encoder, SendDecode, SendDecoder, SendEncode, SendEncoder,
+    };
+    use std::future::Future;
+    use std::marker::PhantomData;
+    use std::pin::Pin;
+
+    pub use build_async::{_async, _await_async, _await_sync};
+    pub use cerdito_derive::_await_send;
+
+    /// Encodes a field whose async future is boxed, e.g. of a recursive type.
+    pub fn encode_boxed<T: Encode + ?Sized, E: Encoder>(
+        value: &T,
+        encoder: &mut E,
+    ) -> Result<(), E::Error> {
+        value.encode(encoder)
+    }
+
+    pub async fn encode_boxed_async<T: Encode + ?Sized, E: Encoder>(
+        value: &T,
+        encoder: &mut E,
+    ) -> Result<(), E::Error> {
+        Box::pin(value.encode_async(encoder)).await
+    }
+
+    // a trait object, so that the future of a recursive type doesn't depend on its own `Send`
+    pub fn encode_boxed_send<'a, T: SendEncode + ?Sized, E: SendEncoder>(
+        value: &'a T,
+        encoder: &'a mut E,
+    ) -> Pin<Box<dyn Future<Output = Result<(), E::Error>> + Send + 'a>> {
+        Box::pin(value.encode_send(encoder))
+    }
+
+    /// Decodes a field whose async future is boxed, e.g. of a recursive type.
+    pub fn decode_boxed<T: Decode, D: Decoder>(decoder: &mut D) -> Result<T, D::Error> {
+        T::decode(decoder)
+    }
+
+    pub async fn decode_boxed_async<T: Decode, D: Decoder>(decoder: &mut D) -> Result<T, D::Error> {
+        Box::pin(T::decode_async(decoder)).await
+    }
+
+    pub fn decode_boxed_send<'a, T: SendDecode + 'a, D: SendDecoder>(
+        decoder: &'a mut D,
+    ) -> Pin<Box<dyn Future<Output = Result<T, D::Error>> + Send + 'a>> {
+        Box::pin(T::decode_send(decoder))
+    }
+
+    /// Tells whether `T` implements `Default`, without requiring it: `elem_default` of
+    /// `&ElemDefault<T>` resolves to `HasElemDefault` if `T: Default`, and to the
+    /// `NoElemDefault` fallback on the autoref otherwise. Fills missing array fields.
+    pub struct ElemDefault<T>(pub PhantomData<T>);
+
+    pub trait HasElemDefault<T> {
+        fn elem_default(&self) -> Option<fn() -> T>;
+    }
+
+    impl<T: Default> HasElemDefault<T> for ElemDefault<T> {
+        fn elem_default(&self) -> Option<fn() -> T> {
+            Some(T::default)
+        }
+    }
+
+    pub trait NoElemDefault<T> {
+        fn elem_default(&self) -> Option<fn() -> T> {
+            None
+        }
+    }
+
+    impl<T> NoElemDefault<T> for &ElemDefault<T> {}
+}
@@ -1,4 +1,5 @@
-use build_async::*;
+#![allow(dead_code, clippy::box_collection, clippy::vec_box)]
+
 use cerdito::{ByteArr, ByteVec, Decode, Encode};
 use multibase::Base;
 use std::fmt::Debug;
@@ -10,6 +11,13 @@ struct SampleStruct {
     b: i32,
 }
 
+// Borrowing view of `SampleStruct`, encodes to the same bytes
+#[derive(Debug, Encode)]
+struct SampleView<'a> {
+    a: &'a str,
+    b: &'a i32,
+}
+
 #[repr(u8)]
 #[derive(Debug, Default, Encode, Decode)]
 enum SampleEnum {
@@ -26,13 +34,8 @@ enum SampleEnum {
 const M: usize = 100;
 
 #[derive(Debug, Default, Encode, Decode)]
-struct S1<
-    T: Debug + Default + Encode + Decode,
-    U: Debug + Default + Encode + Decode,
-    const N: usize,
-> {
+struct S1<T, U, const N: usize> {
     aaa: std::option::Option<std::boxed::Box<String>>,
-    //bbb: [Box<S1<T, U, N>>; M], //TODO: Implement Arr<T; N> types and Default for them
     bbb: Vec<Box<S1<T, U, N>>>,
     ccc: Vec<Option<U>>,
     ddd: Vec<T>,
@@ -47,7 +50,7 @@ struct S3(i32, String);
 
 #[repr(usize)]
 #[derive(Debug, Default, Encode, Decode)]
-enum E1<T: Debug + Default + Encode + Decode, U: Debug + Default + Encode + Decode> {
+enum E1<T, U> {
     #[default]
     A = 0x12,
     A1 = 0x13,
@@ -131,23 +134,10 @@ enum EncryptionKey {
     Aes256CtrIvA(ByteArr<32>) = 1,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Encode, Decode)]
+#[cerdito(transparent)]
 struct FileName(String); // string w/o '/'
 
-impl Encode for FileName {
-    #[_async]
-    fn encode<E: cerdito::Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(self.0.encode(encoder))
-    }
-}
-
-impl Decode for FileName {
-    #[_async]
-    fn decode<D: cerdito::Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        Ok(FileName(_await!(String::decode(decoder))?))
-    }
-}
-
 #[repr(u8)]
 #[derive(Debug, Clone)]
 enum DirectoryEntry {
@@ -181,7 +171,7 @@ struct Link {
 
 //-------main------------
 fn main() -> Result<(), ()> {
-    let mut encoder = rustbif::Encoder { writer: Vec::new() };
+    let mut encoder = rustbif::Encoder::new(Vec::new());
 
     println!("----------------------Encoding/Decoding: recursive enum E1, tuple (SampleEnum:B, (String,)),  SampleStruct Дима, 1024 and 1_u32");
 
@@ -223,9 +213,7 @@ fn main() -> Result<(), ()> {
 
     println!("{:02x?}", encoder.writer);
 
-    let mut decoder = rustbif::Decoder {
-        reader: encoder.writer,
-    };
+    let mut decoder = rustbif::Decoder::new(rustbif::SliceReader::new(&encoder.writer));
     println!("decoding enum E1");
     let v = E1U8I8::decode(&mut decoder);
     dbg!(&v);
@@ -242,7 +230,7 @@ fn main() -> Result<(), ()> {
     let v = u32::decode(&mut decoder);
     dbg!(&v);
 
-    let mut encoder = rustbif::Encoder { writer: Vec::new() };
+    let mut encoder = rustbif::Encoder::new(Vec::new());
 
     println!("----------------------Encoding: String Игорь");
     String::from("Игорь").encode(&mut encoder).unwrap();
@@ -267,7 +255,7 @@ fn main() -> Result<(), ()> {
         .unwrap();
 
     println!("----------------------Encoding: (SampleEnum B a b, ()) and ()");
-    let mut encoder = rustbif::Encoder { writer: Vec::new() };
+    let mut encoder = rustbif::Encoder::new(Vec::new());
     (
         SampleEnum::B {
             a: 'A',
@@ -292,12 +280,21 @@ fn main() -> Result<(), ()> {
     .encode(&mut encoder)
     .unwrap();
 
+    println!("----------------------Encoding: SampleView a b");
+    let mut view_encoder = rustbif::Encoder::new(Vec::new());
+    SampleView { a: "sss", b: &15 }
+        .encode(&mut view_encoder)
+        .unwrap();
+    let mut decoder = rustbif::Decoder::new(rustbif::SliceReader::new(&view_encoder.writer));
+    let v = SampleStruct::decode(&mut decoder).unwrap();
+    assert_eq!((v.a.as_str(), v.b), ("sss", 15));
+
     //--------------
 
-    let mut encoder = rustbif::Encoder {
-        writer: std::io::BufWriter::new(std::fs::File::create("foo.ld").unwrap()),
-    };
-    let mut encoder2 = rustbif::Encoder { writer: Vec::new() };
+    let mut encoder = rustbif::Encoder::new(std::io::BufWriter::new(
+        std::fs::File::create("foo.ld").unwrap(),
+    ));
+    let mut encoder2 = rustbif::Encoder::new(Vec::new());
 
     println!("----------------------ContentIDs!");
     // let hash = core::array::from_fn::<u8, 32, _>(|i| i as u8 + 1);
@@ -327,11 +324,11 @@ fn main() -> Result<(), ()> {
     println!("\n----------------------Done encoding!");
 
     let x = b"abc";
-    let multi58 = multibase::encode(Base::Base58Btc, &x);
-    let multi64 = multibase::encode(Base::Base64, &x);
-    let multi32 = multibase::encode(Base::Base32Lower, &x);
-    let multi16 = multibase::encode(Base::Base16Lower, &x);
-    let multi02 = multibase::encode(Base::Base2, &x);
+    let multi58 = multibase::encode(Base::Base58Btc, x);
+    let multi64 = multibase::encode(Base::Base64, x);
+    let multi32 = multibase::encode(Base::Base32Lower, x);
+    let multi16 = multibase::encode(Base::Base16Lower, x);
+    let multi02 = multibase::encode(Base::Base2, x);
     dbg!((multi64, multi58, multi32, multi16, multi02));
 
     Ok(())
@@ -1,27 +1,6 @@
-use build_async::*;
 use cerdito::{ByteArr, Decode, Encode};
 use std::fmt::Debug;
 
-use tokio::io::AsyncWriteExt;
-
-pub struct TokioReader<T>(pub T);
-impl<T: tokio::io::AsyncRead + std::marker::Unpin> rustbif::Reader for TokioReader<T> {
-    type Error = tokio::io::Error;
-    async fn read_async(&mut self, bytes: &mut [u8]) -> Result<usize, Self::Error> {
-        let n = tokio::io::AsyncReadExt::read(&mut self.0, bytes).await?;
-        Ok(n)
-    }
-}
-
-pub struct TokioWriter<T>(pub T);
-impl<T: tokio::io::AsyncWrite + std::marker::Unpin> rustbif::Writer for TokioWriter<T> {
-    type Error = tokio::io::Error;
-    async fn write_async(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
-        let n = tokio::io::AsyncWriteExt::write(&mut self.0, bytes).await?;
-        Ok(n)
-    }
-}
-
 #[repr(u8)]
 #[derive(Debug, PartialEq, Default, Clone, Encode, Decode)]
 pub enum Hash {
@@ -47,23 +26,10 @@ pub enum EncryptionKey {
     Aes256CtrIvA(ByteArr<32>) = 1,
 }
 
-#[derive(Debug, PartialEq, Default, Clone)]
+#[derive(Debug, PartialEq, Default, Clone, Encode, Decode)]
+#[cerdito(transparent)]
 pub struct FileName(String); // string w/o '/'
 
-impl Encode for FileName {
-    #[_async]
-    fn encode<E: cerdito::Encoder>(&self, encoder: &mut E) -> Result<(), E::Error> {
-        _await!(self.0.encode(encoder))
-    }
-}
-
-impl Decode for FileName {
-    #[_async]
-    fn decode<D: cerdito::Decoder>(decoder: &mut D) -> Result<Self, D::Error> {
-        Ok(FileName(_await!(String::decode(decoder))?))
-    }
-}
-
 #[repr(u8)]
 #[derive(Debug, Default, PartialEq, Clone, Encode, Decode)]
 pub enum DirectoryEntry {
@@ -165,14 +131,12 @@ async fn main() {
         new_field: (content_id.clone(),),
     };
 
-    let mut vec_encoder = rustbif::Encoder { writer: Vec::new() };
+    let mut vec_encoder = rustbif::Encoder::new(Vec::new());
     link.encode_async(&mut vec_encoder).await.unwrap();
     link_v2.encode_async(&mut vec_encoder).await.unwrap();
     directory.encode_async(&mut vec_encoder).await.unwrap();
     directory_v2.encode_async(&mut vec_encoder).await.unwrap();
-    let mut vec_decoder = rustbif::Decoder {
-        reader: vec_encoder.writer,
-    };
+    let mut vec_decoder = rustbif::Decoder::new(rustbif::SliceReader::new(&vec_encoder.writer));
     let link2_v2 = LinkV2::decode_async(&mut vec_decoder).await.unwrap();
     dbg!(&link2_v2);
     let link2 = Link::decode_async(&mut vec_decoder).await.unwrap();
@@ -182,48 +146,46 @@ async fn main() {
         .unwrap();
     dbg!(&directory2_v2);
 
-    // The following panics: Enum "DirectoryEntry" doesn't support variant 4
-    // let directory2 = DirectoryEntry::decode_async(&mut vec_decoder).await.unwrap();
-    // dbg!(&directory2);
+    // Old program, new data: enum "DirectoryEntry" doesn't support variant 4
+    let directory2 = vec_decoder.decode_async::<DirectoryEntry>().await;
+    dbg!(&directory2);
+    let error = directory2.unwrap_err();
+    println!("{}", error);
+    assert!(matches!(
+        error.root(),
+        rustbif::Error::UnknownVariant { tag: 4, .. }
+    ));
 
     let s = S::S1(Box::new(S::S1(Box::new(S::S2))));
 
-    let mut vec_encoder = rustbif::Encoder { writer: Vec::new() };
+    let mut vec_encoder = rustbif::Encoder::new(Vec::new());
     directory.encode_async(&mut vec_encoder).await.unwrap();
     link.encode(&mut vec_encoder).unwrap();
     s.encode(&mut vec_encoder).unwrap();
+    s.encode_async(&mut vec_encoder).await.unwrap();
 
-    // The following does not compile: recursion in an async fn requires boxing
-    //s.encode_async(&mut vec_encoder).await.unwrap();
-
-    let mut file_encoder = rustbif::Encoder {
-        writer: TokioWriter(tokio::io::BufWriter::new(
-            tokio::fs::File::create("foo_async.ld").await.unwrap(),
-        )),
-    };
+    let mut file_encoder = rustbif::Encoder::from_tokio(tokio::io::BufWriter::new(
+        tokio::fs::File::create("foo_async.ld").await.unwrap(),
+    ));
     directory.encode_async(&mut file_encoder).await.unwrap();
     link.encode_async(&mut file_encoder).await.unwrap();
-    file_encoder.writer.0.shutdown().await.unwrap();
+    s.encode_async(&mut file_encoder).await.unwrap();
+    file_encoder.writer.shutdown().await.unwrap();
 
-    let mut vec_decoder = rustbif::Decoder {
-        reader: vec_encoder.writer,
-    };
+    let mut vec_decoder = rustbif::Decoder::new(rustbif::SliceReader::new(&vec_encoder.writer));
     let directory2 = DirectoryEntry::decode(&mut vec_decoder).unwrap();
     let link2 = Link::decode_async(&mut vec_decoder).await.unwrap();
     let s2 = S::decode(&mut vec_decoder).unwrap();
+    let s2_async = S::decode_async(&mut vec_decoder).await.unwrap();
 
-    // The following does not compile: recursion in an async fn requires boxing
-    //let s2 = S::decode_async(&mut vec_decoder).await.unwrap();
-
-    let mut file_decoder = rustbif::Decoder {
-        reader: TokioReader(tokio::io::BufReader::new(
-            tokio::fs::File::open("foo_async.ld").await.unwrap(),
-        )),
-    };
+    let mut file_decoder = rustbif::Decoder::from_tokio(tokio::io::BufReader::new(
+        tokio::fs::File::open("foo_async.ld").await.unwrap(),
+    ));
     let directory3 = DirectoryEntry::decode_async(&mut file_decoder)
         .await
         .unwrap();
     let link3 = Link::decode_async(&mut file_decoder).await.unwrap();
+    let s3 = S::decode_async(&mut file_decoder).await.unwrap();
 
     dbg!(&directory3);
     dbg!(&link3);
@@ -234,4 +196,6 @@ async fn main() {
     assert_eq!(link, link2);
     assert_eq!(link, link3);
     assert_eq!(s, s2);
+    assert_eq!(s, s2_async);
+    assert_eq!(s, s3);
 }
@@ -0,0 +1,340 @@
+//! `Reader`/`Writer` adapters for async I/O traits of `tokio` and `futures-io`.
+//!
+//! The adapters are non-blocking only: their blocking methods fail with
+//! `ErrorKind::Unsupported`, so use the `*_async` API of `Encoder`/`Decoder` with them.
+
+use crate::{Decoder, Encoder, Reader, SendReader, SendWriter, Writer};
+use cerdito_derive::send_variant;
+use std::io;
+#[cfg(feature = "futures-io")]
+use std::{future::poll_fn, pin::Pin};
+
+fn blocking_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "blocking i/o is not supported by async adapters",
+    )
+}
+
+//------ tokio -------
+
+/// Reads from a `tokio::io::AsyncRead`, filling the whole buffer or failing with
+/// `ErrorKind::UnexpectedEof`.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct TokioReader<T>(pub T);
+
+#[cfg(feature = "tokio")]
+#[send_variant(T: Send)]
+impl<T: tokio::io::AsyncRead + Unpin> Reader for TokioReader<T> {
+    type Error = io::Error;
+    fn read_exact(&mut self, _bytes: &mut [u8]) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    fn is_eof(error: &Self::Error) -> bool {
+        error.kind() == io::ErrorKind::UnexpectedEof
+    }
+    async fn read_exact_async(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        tokio::io::AsyncReadExt::read_exact(&mut self.0, bytes).await?;
+        Ok(())
+    }
+}
+
+/// Writes to a `tokio::io::AsyncWrite`.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct TokioWriter<T>(pub T);
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncWrite + Unpin> TokioWriter<T> {
+    /// Flushes and shuts down the underlying writer, e.g. after the last value is encoded.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        tokio::io::AsyncWriteExt::shutdown(&mut self.0).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[send_variant(T: Send)]
+impl<T: tokio::io::AsyncWrite + Unpin> Writer for TokioWriter<T> {
+    type Error = io::Error;
+    fn write_all(&mut self, _bytes: &[u8]) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    async fn write_all_async(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        tokio::io::AsyncWriteExt::write_all(&mut self.0, bytes).await
+    }
+    async fn flush_async(&mut self) -> Result<(), Self::Error> {
+        tokio::io::AsyncWriteExt::flush(&mut self.0).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncRead + Unpin> Decoder<TokioReader<T>> {
+    pub fn from_tokio(reader: T) -> Self {
+        Self::new(TokioReader(reader))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: tokio::io::AsyncWrite + Unpin> Encoder<TokioWriter<T>> {
+    pub fn from_tokio(writer: T) -> Self {
+        Self::new(TokioWriter(writer))
+    }
+}
+
+//------ futures-io -------
+
+/// Reads from a `futures_io::AsyncRead`, filling the whole buffer or failing with
+/// `ErrorKind::UnexpectedEof`.
+#[cfg(feature = "futures-io")]
+#[derive(Debug)]
+pub struct FuturesReader<T>(pub T);
+
+#[cfg(feature = "futures-io")]
+#[send_variant(T: Send)]
+impl<T: futures_io::AsyncRead + Unpin> Reader for FuturesReader<T> {
+    type Error = io::Error;
+    fn read_exact(&mut self, _bytes: &mut [u8]) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    fn is_eof(error: &Self::Error) -> bool {
+        error.kind() == io::ErrorKind::UnexpectedEof
+    }
+    async fn read_exact_async(&mut self, mut bytes: &mut [u8]) -> Result<(), Self::Error> {
+        while !bytes.is_empty() {
+            match poll_fn(|cx| Pin::new(&mut self.0).poll_read(cx, bytes)).await {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => bytes = &mut std::mem::take(&mut bytes)[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes to a `futures_io::AsyncWrite`.
+#[cfg(feature = "futures-io")]
+#[derive(Debug)]
+pub struct FuturesWriter<T>(pub T);
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncWrite + Unpin> FuturesWriter<T> {
+    /// Flushes and closes the underlying writer, e.g. after the last value is encoded.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        poll_fn(|cx| Pin::new(&mut self.0).poll_close(cx)).await
+    }
+}
+
+#[cfg(feature = "futures-io")]
+#[send_variant(T: Send)]
+impl<T: futures_io::AsyncWrite + Unpin> Writer for FuturesWriter<T> {
+    type Error = io::Error;
+    fn write_all(&mut self, _bytes: &[u8]) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Err(blocking_unsupported())
+    }
+    async fn write_all_async(&mut self, mut bytes: &[u8]) -> Result<(), Self::Error> {
+        while !bytes.is_empty() {
+            match poll_fn(|cx| Pin::new(&mut self.0).poll_write(cx, bytes)).await {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => bytes = &bytes[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+    async fn flush_async(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|cx| Pin::new(&mut self.0).poll_flush(cx)).await
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncRead + Unpin> Decoder<FuturesReader<T>> {
+    pub fn from_futures_io(reader: T) -> Self {
+        Self::new(FuturesReader(reader))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<T: futures_io::AsyncWrite + Unpin> Encoder<FuturesWriter<T>> {
+    pub fn from_futures_io(writer: T) -> Self {
+        Self::new(FuturesWriter(writer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tokio")]
+    use crate::rustbif::tests::decode_send;
+    use crate::rustbif::tests::encode;
+    use crate::Error;
+    use cerdito::Encode;
+
+    /// Async reader and writer that transfer one byte per poll, like a slow socket.
+    #[derive(Default)]
+    struct Trickle {
+        data: Vec<u8>,
+        pos: usize,
+        closed: bool,
+    }
+
+    #[cfg(feature = "tokio")]
+    impl tokio::io::AsyncRead for Trickle {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if let Some(&byte) = self.data.get(self.pos) {
+                buf.put_slice(&[byte]);
+                self.pos += 1;
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    impl tokio::io::AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.data.extend_from_slice(&buf[..1]);
+            std::task::Poll::Ready(Ok(1))
+        }
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+        fn poll_shutdown(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            self.closed = true;
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "futures-io")]
+    impl futures_io::AsyncRead for Trickle {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut [u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            let n = match self.data.get(self.pos) {
+                Some(&byte) if !buf.is_empty() => {
+                    buf[0] = byte;
+                    1
+                }
+                _ => 0,
+            };
+            self.pos += n;
+            std::task::Poll::Ready(Ok(n))
+        }
+    }
+
+    #[cfg(feature = "futures-io")]
+    impl futures_io::AsyncWrite for Trickle {
+        fn poll_write(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.data.extend_from_slice(&buf[..1]);
+            std::task::Poll::Ready(Ok(1))
+        }
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+        fn poll_close(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            self.closed = true;
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_adapters() {
+        let value = (String::from("hello"), vec![1_u32, 300, 70000]);
+        let bytes = encode(&value);
+
+        let mut encoder = Encoder::from_tokio(Trickle::default());
+        value.encode_async(&mut encoder).await.unwrap();
+        encoder.writer.shutdown().await.unwrap();
+        assert!(encoder.writer.0.closed);
+        assert_eq!(encoder.writer.0.data, bytes);
+        assert!(value.encode(&mut encoder).is_err()); // blocking i/o is unsupported
+
+        let mut decoder = Decoder::from_tokio(Trickle {
+            data: bytes.clone(),
+            ..Trickle::default()
+        });
+        let decoded = decoder.decode_async::<(String, Vec<u32>)>().await.unwrap();
+        assert_eq!(decoded, value);
+        let reader = TokioReader(std::io::Cursor::new(bytes.clone()));
+        assert_eq!(decode_send::<(String, Vec<u32>), _>(reader).await, value);
+
+        let mut decoder = Decoder::from_tokio(Trickle {
+            data: bytes[..bytes.len() - 1].to_vec(),
+            ..Trickle::default()
+        });
+        let error = decoder
+            .decode_async::<(String, Vec<u32>)>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error.root(), Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+        );
+    }
+
+    #[cfg(feature = "futures-io")]
+    #[tokio::test]
+    async fn test_futures_io_adapters() {
+        let value = (String::from("hello"), vec![1_u32, 300, 70000]);
+        let bytes = encode(&value);
+
+        let mut encoder = Encoder::from_futures_io(Trickle::default());
+        value.encode_async(&mut encoder).await.unwrap();
+        encoder.writer.shutdown().await.unwrap();
+        assert!(encoder.writer.0.closed);
+        assert_eq!(encoder.writer.0.data, bytes);
+        assert!(value.encode(&mut encoder).is_err()); // blocking i/o is unsupported
+
+        let mut decoder = Decoder::from_futures_io(Trickle {
+            data: bytes.clone(),
+            ..Trickle::default()
+        });
+        let decoded = decoder.decode_async::<(String, Vec<u32>)>().await.unwrap();
+        assert_eq!(decoded, value);
+
+        let mut decoder = Decoder::from_futures_io(Trickle {
+            data: bytes[..bytes.len() - 1].to_vec(),
+            ..Trickle::default()
+        });
+        let error = decoder
+            .decode_async::<(String, Vec<u32>)>()
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error.root(), Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof)
+        );
+    }
+}
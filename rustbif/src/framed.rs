@@ -0,0 +1,384 @@
+//! Length-delimited framing of encoded values.
+//!
+//! Every frame is a varbytes element: a header with the byte length of the encoded value
+//! followed by the value itself. A reader can thus find the end of a value without decoding
+//! it, skip or forward it, and resume after a value that fails to decode.
+
+use crate::rustbif::{usize_from, VarIntLen, CHUNK_LEN};
+use crate::{Encoder, Error, Limit, Reader, SendReader, SendWriter, Writer};
+use build_async::*;
+use cerdito::{Encode, SendEncode};
+use cerdito_derive::{_await_send, send_variant};
+use std::fmt::{self, Display};
+use std::marker::PhantomData;
+
+/// Error of `FramedReader::forward_frame`, from reading the frame or from writing it.
+#[derive(Debug)]
+pub enum ForwardError<R, W> {
+    Read(Error<R>),
+    Write(Error<W>),
+}
+
+impl<R: Display, W: Display> Display for ForwardError<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read(e) => write!(f, "frame read error: {}", e),
+            Self::Write(e) => write!(f, "frame write error: {}", e),
+        }
+    }
+}
+
+impl<R, W> std::error::Error for ForwardError<R, W>
+where
+    R: std::error::Error + 'static,
+    W: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(e) => Some(e),
+            Self::Write(e) => Some(e),
+        }
+    }
+}
+
+/// In-memory writer with the error type of the framed writer; writing to it never fails.
+struct FrameBuf<E>(Vec<u8>, PhantomData<fn() -> E>);
+
+impl<E> Writer for FrameBuf<E> {
+    type Error = E;
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Writes values as frames, each encoded value prefixed by its byte length.
+pub struct FramedWriter<W: Writer> {
+    pub writer: W,
+    buf: Vec<u8>,
+}
+
+#[send_variant]
+impl<W: Writer> FramedWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Encodes the value into a buffer and writes it as a frame. Only the i/o is async.
+    #[_async]
+    pub fn write<T: Encode + ?Sized>(&mut self, value: &T) -> Result<(), Error<W::Error>> {
+        let mut encoder = Encoder::new(FrameBuf(std::mem::take(&mut self.buf), PhantomData));
+        let encoded = value.encode(&mut encoder);
+        let mut buf = encoder.writer.0;
+        let result = match encoded {
+            Ok(()) => _await!(self.write_frame(&buf)),
+            Err(e) => Err(e),
+        };
+        buf.clear();
+        self.buf = buf;
+        result
+    }
+
+    /// Writes already encoded bytes as a frame, e.g. one read by `FramedReader::read_frame`.
+    #[_async]
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<(), Error<W::Error>> {
+        _await!(VarIntLen::from_byte_size(frame.len() as u64).write(&mut self.writer))
+            .map_err(Error::Io)?;
+        _await!(self.writer.write_all(frame)).map_err(Error::Io)
+    }
+
+    #[_async]
+    pub fn flush(&mut self) -> Result<(), Error<W::Error>> {
+        _await!(self.writer.flush()).map_err(Error::Io)
+    }
+}
+
+/// Reads frames written by `FramedWriter`.
+///
+/// A frame is decoded from the returned slice, e.g.
+/// `Decoder::new(SliceReader::new(frame)).decode::<T>()`, so a value that fails to decode
+/// doesn't affect the following frames.
+pub struct FramedReader<R: Reader> {
+    pub reader: R,
+    max_frame_len: usize,
+    frame: Vec<u8>,
+}
+
+#[send_variant]
+impl<R: Reader> FramedReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_max_frame_len(reader, usize::MAX)
+    }
+
+    /// Frames longer than `max_frame_len` fail with `Error::LimitExceeded(Limit::BytesLen)`.
+    pub fn with_max_frame_len(reader: R, max_frame_len: usize) -> Self {
+        Self {
+            reader,
+            max_frame_len,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Length of the next frame, `None` if the input ends before it.
+    #[_async]
+    fn read_frame_len(&mut self) -> Result<Option<usize>, Error<R::Error>> {
+        let mut first = [0_u8];
+        match _await!(self.reader.read_exact(&mut first)) {
+            Ok(()) => {}
+            Err(e) if R::is_eof(&e) => return Ok(None),
+            Err(e) => return Err(Error::Io(e)),
+        }
+        let (header, _) =
+            _await!(VarIntLen::from_header(first[0], &mut self.reader)).map_err(Error::Io)?;
+        let len = match header {
+            VarIntLen::Zero => 0,
+            VarIntLen::ByteSize(buf) => usize_from(u64::from_le_bytes(buf))?,
+            _ => return Err(Error::BadBytesHeader),
+        };
+        if len > self.max_frame_len {
+            return Err(Error::LimitExceeded(Limit::BytesLen));
+        }
+        Ok(Some(len))
+    }
+
+    /// Reads the next frame. Returns `None` only if the input ends cleanly before the first
+    /// header byte, as told by `Reader::is_eof`; input that ends later in the frame fails with
+    /// the reader's end of input error in `Error::Io`. The buffer grows as the bytes arrive,
+    /// so a bogus length can't allocate much more memory than the input has.
+    #[_async]
+    pub fn read_frame(&mut self) -> Result<Option<&[u8]>, Error<R::Error>> {
+        let Some(len) = _await!(self.read_frame_len())? else {
+            return Ok(None);
+        };
+        self.frame.clear();
+        while self.frame.len() < len {
+            let start = self.frame.len();
+            self.frame.resize(start + (len - start).min(CHUNK_LEN), 0);
+            _await!(self.reader.read_exact(&mut self.frame[start..])).map_err(Error::Io)?;
+        }
+        Ok(Some(&self.frame))
+    }
+
+    /// Skips the next frame and returns its length. Returns `None` and fails on input that
+    /// ends mid-frame as `read_frame` does.
+    #[_async]
+    pub fn skip_frame(&mut self) -> Result<Option<usize>, Error<R::Error>> {
+        let Some(len) = _await!(self.read_frame_len())? else {
+            return Ok(None);
+        };
+        let mut buf = [0_u8; 256];
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(buf.len());
+            _await!(self.reader.read_exact(&mut buf[..n])).map_err(Error::Io)?;
+            left -= n;
+        }
+        Ok(Some(len))
+    }
+
+    /// Copies the next frame to the writer without buffering it and returns its length.
+    /// Returns `None` and fails on input that ends mid-frame as `read_frame` does, with the
+    /// error in `ForwardError::Read`.
+    #[_async]
+    pub fn forward_frame<W: Writer>(
+        &mut self,
+        writer: &mut FramedWriter<W>,
+    ) -> Result<Option<usize>, ForwardError<R::Error, W::Error>> {
+        let Some(len) = _await!(self.read_frame_len()).map_err(ForwardError::Read)? else {
+            return Ok(None);
+        };
+        let read_error = |e: R::Error| ForwardError::Read(Error::Io(e));
+        let write_error = |e: W::Error| ForwardError::Write(Error::Io(e));
+        _await!(VarIntLen::from_byte_size(len as u64).write(&mut writer.writer))
+            .map_err(write_error)?;
+        let mut buf = [0_u8; 256];
+        let mut left = len;
+        while left > 0 {
+            let n = left.min(buf.len());
+            _await!(self.reader.read_exact(&mut buf[..n])).map_err(read_error)?;
+            _await!(writer.writer.write_all(&buf[..n])).map_err(write_error)?;
+            left -= n;
+        }
+        Ok(Some(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rustbif::tests::encode;
+    use crate::{Decoder, Limit, SliceReader, UnexpectedEof};
+    use cerdito::ByteVec;
+
+    #[derive(Debug, PartialEq, cerdito::Encode, cerdito::Decode, cerdito::SendEncode)]
+    struct Message {
+        id: u32,
+        text: String,
+    }
+
+    fn message(id: u32) -> Message {
+        Message {
+            id,
+            text: format!("message {}", id),
+        }
+    }
+
+    fn decode_frame<T: cerdito::Decode>(frame: &[u8]) -> Result<T, Error<UnexpectedEof>> {
+        Decoder::new(SliceReader::new(frame)).decode()
+    }
+
+    #[test]
+    fn test_framed() {
+        let mut writer = FramedWriter::new(Vec::new());
+        writer.write(&message(1)).unwrap();
+        writer.write_frame(&[0xff]).unwrap(); // doesn't decode as `Message`
+        writer.write(&message(2)).unwrap();
+        writer.write(&message(3)).unwrap();
+        writer.write_frame(&[]).unwrap();
+        let bytes = writer.writer;
+
+        // a frame is a varbytes element holding the encoded value
+        let frame = encode(&message(1));
+        assert!(bytes.starts_with(&encode(&ByteVec(frame.clone()))));
+
+        let mut reader = FramedReader::new(&bytes[..]);
+        assert_eq!(reader.read_frame().unwrap(), Some(&frame[..]));
+        assert_eq!(decode_frame::<Message>(&frame).unwrap(), message(1));
+        // the stream stays in sync after a value that fails to decode
+        assert!(decode_frame::<Message>(reader.read_frame().unwrap().unwrap()).is_err());
+        let mut forwarded = FramedWriter::new(Vec::new());
+        let len = reader.forward_frame(&mut forwarded).unwrap().unwrap();
+        assert_eq!(reader.skip_frame().unwrap(), Some(len));
+        assert_eq!(reader.read_frame().unwrap(), Some(&[] as &[u8]));
+        // the input ends between frames
+        assert_eq!(reader.read_frame().unwrap(), None);
+        assert_eq!(reader.skip_frame().unwrap(), None);
+        assert_eq!(reader.forward_frame(&mut forwarded).unwrap(), None);
+        // the input ends inside a frame, here after its header, following a whole one
+        let first = encode(&ByteVec(frame.clone())).len();
+        let mut reader = FramedReader::new(SliceReader::new(&bytes[..first + 1]));
+        assert_eq!(reader.read_frame().unwrap(), Some(&frame[..]));
+        assert!(matches!(reader.read_frame(), Err(Error::Io(UnexpectedEof))));
+        let mut reader = FramedReader::new(SliceReader::new(&bytes[..first + 1]));
+        assert_eq!(reader.skip_frame().unwrap(), Some(frame.len()));
+        assert!(matches!(reader.skip_frame(), Err(Error::Io(UnexpectedEof))));
+
+        let mut reader = FramedReader::new(SliceReader::new(&forwarded.writer));
+        let frame = reader.read_frame().unwrap().unwrap();
+        assert_eq!(frame.len(), len);
+        assert_eq!(decode_frame::<Message>(frame).unwrap(), message(2));
+        assert!(reader.reader.is_empty());
+        assert_eq!(reader.read_frame().unwrap(), None);
+
+        let mut reader = FramedReader::with_max_frame_len(&bytes[..], 8);
+        assert!(matches!(
+            reader.read_frame(),
+            Err(Error::LimitExceeded(Limit::BytesLen))
+        ));
+        let mut reader = FramedReader::new(&[0x01][..]);
+        assert!(matches!(reader.read_frame(), Err(Error::BadBytesHeader)));
+
+        // a frame cut in its header or its body is an error, not the end of the input
+        let cut = |len: usize| SliceReader::new(&bytes[..len]);
+        assert!(matches!(
+            FramedReader::new(cut(1)).read_frame(),
+            Err(Error::Io(UnexpectedEof))
+        ));
+        assert!(matches!(
+            FramedReader::new(cut(2)).skip_frame(),
+            Err(Error::Io(UnexpectedEof))
+        ));
+        let mut long = FramedWriter::new(Vec::new());
+        long.write_frame(&[7; 100]).unwrap();
+        let mut reader = FramedReader::new(SliceReader::new(&long.writer[..1]));
+        assert!(matches!(reader.read_frame(), Err(Error::Io(UnexpectedEof))));
+
+        // a reader that can't tell the end of the input reports it as an error
+        struct Opaque<'a>(&'a [u8]);
+        impl Reader for Opaque<'_> {
+            type Error = UnexpectedEof;
+            fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+                SliceReader::new(std::mem::take(&mut self.0)).read_exact(bytes)
+            }
+        }
+        let mut reader = FramedReader::new(Opaque(&[]));
+        assert!(matches!(reader.read_frame(), Err(Error::Io(UnexpectedEof))));
+
+        // forward errors tell whether reading or writing failed
+        let mut reader = FramedReader::new(&bytes[..]);
+        let mut buf = [0_u8; 4];
+        let mut small = FramedWriter::new(&mut buf[..]);
+        let r = reader.forward_frame(&mut small);
+        assert!(matches!(r, Err(ForwardError::Write(Error::Io(ref e)))
+            if e.kind() == std::io::ErrorKind::WriteZero));
+        let mut reader = FramedReader::new(cut(2));
+        let r = reader.forward_frame(&mut forwarded);
+        assert!(matches!(
+            r,
+            Err(ForwardError::Read(Error::Io(UnexpectedEof)))
+        ));
+        let mut reader = FramedReader::with_max_frame_len(&bytes[..], 8);
+        let r = reader.forward_frame(&mut forwarded);
+        assert!(matches!(
+            r,
+            Err(ForwardError::Read(Error::LimitExceeded(Limit::BytesLen)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_framed_async() {
+        let mut writer = FramedWriter::new(Vec::new());
+        let mut sync_writer = FramedWriter::new(Vec::new());
+        for id in 1..=3 {
+            writer.write_async(&message(id)).await.unwrap();
+            sync_writer.write(&message(id)).unwrap();
+        }
+        writer.flush_async().await.unwrap();
+        assert_eq!(writer.writer, sync_writer.writer);
+
+        let mut reader = FramedReader::new(&writer.writer[..]);
+        let frame = reader.read_frame_async().await.unwrap().unwrap();
+        assert_eq!(decode_frame::<Message>(frame).unwrap(), message(1));
+        assert!(reader.skip_frame_async().await.unwrap().is_some());
+        let mut forwarded = FramedWriter::new(Vec::new());
+        let len = reader.forward_frame_async(&mut forwarded).await.unwrap();
+        assert!(len.is_some());
+        assert_eq!(reader.read_frame_async().await.unwrap(), None);
+        assert_eq!(reader.skip_frame_async().await.unwrap(), None);
+        let r = reader.forward_frame_async(&mut forwarded).await.unwrap();
+        assert_eq!(r, None);
+
+        let mut reader = FramedReader::new(&forwarded.writer[..]);
+        let frame = reader.read_frame_async().await.unwrap().unwrap();
+        assert_eq!(frame.len(), len.unwrap());
+        assert_eq!(decode_frame::<Message>(frame).unwrap(), message(3));
+        assert_eq!(reader.read_frame_async().await.unwrap(), None);
+        let mut reader = FramedReader::new(&writer.writer[..1]);
+        let r = reader.read_frame_async().await;
+        assert!(matches!(r, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_framed_send() {
+        let mut writer = FramedWriter::new(Vec::new());
+        let task = tokio::spawn(async move {
+            writer.write_send(&message(1)).await.unwrap();
+            writer.writer
+        });
+        let bytes = task.await.unwrap();
+        let mut reader = FramedReader::new(std::io::Cursor::new(bytes));
+        let task = tokio::spawn(async move {
+            let frame = reader.read_frame_send().await.unwrap().unwrap().to_vec();
+            assert_eq!(reader.read_frame_send().await.unwrap(), None);
+            frame
+        });
+        let frame = task.await.unwrap();
+        assert_eq!(decode_frame::<Message>(&frame).unwrap(), message(1));
+    }
+}
@@ -1,2 +1,13 @@
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod async_io;
+mod framed;
 mod rustbif;
-pub use crate::rustbif::{Decoder, Encoder, Reader, Writer};
+#[cfg(feature = "futures-io")]
+pub use crate::async_io::{FuturesReader, FuturesWriter};
+#[cfg(feature = "tokio")]
+pub use crate::async_io::{TokioReader, TokioWriter};
+pub use crate::framed::{ForwardError, FramedReader, FramedWriter};
+pub use crate::rustbif::{
+    Decoder, DecoderConfig, Encoder, Error, Limit, Reader, SendReader, SendWriter, SliceReader,
+    UnexpectedEof, Writer,
+};
@@ -1,10 +1,146 @@
 #![allow(async_fn_in_trait)]
+#![allow(clippy::unusual_byte_groupings)]
 
 use build_async::*;
+use cerdito_derive::{_await_send, send_variant};
 use std::convert::TryInto;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display};
 use zigzag::ZigZag;
 
+//------ Error -------
+
+#[derive(Debug)]
+pub enum Error<E> {
+    /// Error reported by the underlying reader or writer.
+    Io(E),
+    /// Expected a varstruct header (sequence).
+    BadSeqHeader,
+    /// Expected a varstruct header (struct or tuple).
+    BadStructHeader,
+    /// Expected a varenum or varint header.
+    BadEnumHeader,
+    /// Expected a varbytes header.
+    BadBytesHeader,
+    /// Expected a varint header.
+    BadVarIntHeader,
+    /// Varint value does not fit into the requested integer type.
+    IntegerOverflow,
+    /// Byte size or element count does not fit into `usize`.
+    LengthOverflow,
+    /// Byte array size is not a multiple of the element size.
+    BadArraySize { size: usize, elem_size: usize },
+    /// Value is not a valid `char`.
+    InvalidChar(u32),
+    /// String is not valid UTF-8.
+    InvalidUtf8(std::string::FromUtf8Error),
+    /// Enum tag does not match any known variant.
+    UnknownVariant { enum_name: String, tag: u32 },
+    /// Sequence, array or byte string has an unexpected length.
+    InvalidLength { expected: usize, got: usize },
+    /// Required struct field is not present in the input.
+    MissingField(String),
+    /// Error reported by an `Encode` or `Decode` implementation.
+    Custom(String),
+    /// Input exceeds one of the `DecoderConfig` limits.
+    LimitExceeded(Limit),
+    /// Error annotated with the input offset and the path of the item that failed to decode.
+    At {
+        position: u64,
+        path: String,
+        error: Box<Error<E>>,
+    },
+}
+
+impl<E> Error<E> {
+    /// Underlying error without the position and path annotation.
+    pub fn root(&self) -> &Error<E> {
+        match self {
+            Self::At { error, .. } => error.root(),
+            _ => self,
+        }
+    }
+}
+
+/// Limit of `DecoderConfig` that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    InputBytes,
+    BytesLen,
+    SeqLen,
+    Depth,
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {}", e),
+            Self::BadSeqHeader => write!(f, "bad seq header"),
+            Self::BadStructHeader => write!(f, "bad varstruct header"),
+            Self::BadEnumHeader => write!(f, "bad varenum header"),
+            Self::BadBytesHeader => write!(f, "bad varbytes header"),
+            Self::BadVarIntHeader => write!(f, "bad varint header"),
+            Self::IntegerOverflow => write!(f, "integer overflow"),
+            Self::LengthOverflow => write!(f, "length overflow"),
+            Self::BadArraySize { size, elem_size } => write!(
+                f,
+                "byte array size {} is not a multiple of element size {}",
+                size, elem_size
+            ),
+            Self::InvalidChar(v) => write!(f, "invalid char {:#x}", v),
+            Self::InvalidUtf8(e) => write!(f, "invalid utf-8 string: {}", e),
+            Self::UnknownVariant { enum_name, tag } => {
+                write!(f, "enum {} doesn't support variant {}", enum_name, tag)
+            }
+            Self::InvalidLength { expected, got } => {
+                write!(f, "invalid length {}, expected {}", got, expected)
+            }
+            Self::MissingField(name) => write!(f, "missing field {}", name),
+            Self::Custom(msg) => write!(f, "{}", msg),
+            Self::LimitExceeded(limit) => write!(f, "decoder limit exceeded: {:?}", limit),
+            Self::At {
+                position,
+                path,
+                error,
+            } => {
+                if path.is_empty() {
+                    write!(f, "{} at byte {}", error, position)
+                } else {
+                    write!(f, "{} at byte {} ({})", error, position, path)
+                }
+            }
+        }
+    }
+}
+
+impl<E> cerdito::Error for Error<E> {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+    fn unknown_variant(enum_name: &str, tag: u32) -> Self {
+        Self::UnknownVariant {
+            enum_name: enum_name.to_string(),
+            tag,
+        }
+    }
+    fn invalid_length(expected: usize, got: usize) -> Self {
+        Self::InvalidLength { expected, got }
+    }
+    fn missing_field(name: &str) -> Self {
+        Self::MissingField(name.to_string())
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidUtf8(e) => Some(e),
+            Self::At { error, .. } => error.source(),
+            _ => None,
+        }
+    }
+}
+
 //------ varintlen -------
 
 const M_VALUE: u8 = 0b00_000000; // 0x00
@@ -16,7 +152,7 @@ const M_STRUCT_LEN: u8 = 0b111110_00; // 0xf8
 const M_ENUM_LEN: u8 = 0b111111_00; // 0xfC
 
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum VarIntLen {
+pub(crate) enum VarIntLen {
     Zero,               // Value(0), UnitEnumTag(0), ByteSize(0), or StructLen(0) followed by nothing
     Value([u8; 16]), // Value(value = 1..=95, 0..2^128) or UnitEnumTag(tag = 1..=95, 0..2^128) followed by nothing
     ByteSize([u8; 8]), // ByteSize(size = 1..=64, 0..2^64) followed by `size` bytes of data
@@ -24,24 +160,29 @@ enum VarIntLen {
     EnumTag([u8; 4]), // EnumTag(tag = 0..=31 (96..=127), 0..2^32) followed by 1 element
 }
 
+#[send_variant]
 impl VarIntLen {
+    #[allow(dead_code)]
     fn new() -> Self {
         Self::Zero
     }
+    #[allow(dead_code)]
     fn from_value_f32(value: f32) -> Self {
         Self::from_value_slice(&value.to_le_bytes())
     }
+    #[allow(dead_code)]
     fn from_value_f64(value: f64) -> Self {
         Self::from_value_slice(&value.to_le_bytes())
     }
     fn from_value_u32(value: u32) -> Self {
         Self::from_value_slice(&value.to_le_bytes())
     }
+    #[allow(dead_code)]
     fn from_value_u128(value: u128) -> Self {
         Self::from_value_slice(&value.to_le_bytes())
     }
     fn from_value_slice(bytes: &[u8]) -> Self {
-        if bytes.iter().rposition(|x| *x != 0) == None {
+        if bytes.iter().rposition(|x| *x != 0).is_none() {
             Self::Zero
         } else {
             let mut buf = [0; 16];
@@ -49,11 +190,11 @@ impl VarIntLen {
             Self::Value(buf)
         }
     }
-    fn from_byte_size(size: u64) -> Self {
+    pub(crate) fn from_byte_size(size: u64) -> Self {
         Self::from_byte_size_slice(&size.to_le_bytes())
     }
     fn from_byte_size_slice(bytes: &[u8]) -> Self {
-        if bytes.iter().rposition(|x| *x != 0) == None {
+        if bytes.iter().rposition(|x| *x != 0).is_none() {
             Self::Zero
         } else {
             let mut buf = [0; 8];
@@ -65,7 +206,7 @@ impl VarIntLen {
         Self::from_struct_len_slice(&len.to_le_bytes())
     }
     fn from_struct_len_slice(bytes: &[u8]) -> Self {
-        if bytes.iter().rposition(|x| *x != 0) == None {
+        if bytes.iter().rposition(|x| *x != 0).is_none() {
             Self::Zero
         } else {
             let mut buf = [0; 4];
@@ -83,11 +224,21 @@ impl VarIntLen {
     }
 
     #[_async]
-    fn from_reader<R: Reader>(reader: &mut R) -> Result<(Self, usize), R::Error> {
-        let mut cnt: usize = 0;
+    pub(crate) fn from_reader<R: Reader>(reader: &mut R) -> Result<(Self, usize), R::Error> {
+        let mut header = [0_u8];
+        _await!(reader.read_exact(&mut header))?;
+        _await!(Self::from_header(header[0], reader))
+    }
+
+    /// Reads the rest of the element header whose first byte `header` is already read.
+    #[_async]
+    pub(crate) fn from_header<R: Reader>(
+        header: u8,
+        reader: &mut R,
+    ) -> Result<(Self, usize), R::Error> {
+        let mut cnt: usize = 1;
         let mut buf = [0_u8; 16];
-        cnt += _await!(reader.read(&mut buf[..1]))?;
-        let header = buf[0];
+        buf[0] = header;
         match header.leading_ones() {
             0 => Ok((
                 match header {
@@ -114,32 +265,36 @@ impl VarIntLen {
             3 => {
                 let mut buf = [0_u8; 16];
                 let len: usize = ((header & 0b00001111) + 1).into();
-                cnt += _await!(reader.read(&mut buf[..len]))?;
+                _await!(reader.read_exact(&mut buf[..len]))?;
+                cnt += len;
                 Ok((Self::Value(buf), cnt))
             }
             4 => {
                 let mut buf = [0_u8; 8];
                 let len: usize = ((header & 0b00000111) + 1).into();
-                cnt += _await!(reader.read(&mut buf[..len]))?;
+                _await!(reader.read_exact(&mut buf[..len]))?;
+                cnt += len;
                 Ok((Self::ByteSize(buf), cnt))
             }
             5 => {
                 let mut buf = [0_u8; 4];
                 let len: usize = ((header & 0b00000011) + 1).into();
-                cnt += _await!(reader.read(&mut buf[..len]))?;
+                _await!(reader.read_exact(&mut buf[..len]))?;
+                cnt += len;
                 Ok((Self::StructLen(buf), cnt))
             }
             _ => {
                 let mut buf = [0_u8; 4];
                 let len: usize = ((header & 0b00000011) + 1).into();
-                cnt += _await!(reader.read(&mut buf[..len]))?;
+                _await!(reader.read_exact(&mut buf[..len]))?;
+                cnt += len;
                 Ok((Self::EnumTag(buf), cnt))
             }
         }
     }
 
     #[_async]
-    fn write<W: Writer>(&self, writer: &mut W) -> Result<usize, W::Error> {
+    pub(crate) fn write<W: Writer>(&self, writer: &mut W) -> Result<usize, W::Error> {
         let (mask1, mask2, bytes, corr_sub, corr_add, threshold) = match self {
             Self::Zero => (M_VALUE, M_VALUE_LEN, &[0][..], 0, 0, 0),
             Self::Value(buf) => (M_VALUE, M_VALUE_LEN, &buf[..], 0, 0, 95),
@@ -148,19 +303,22 @@ impl VarIntLen {
             Self::StructLen(buf) => (M_STRUCT, M_STRUCT_LEN, &buf[..], 1, 0, 32),
         };
         let mut cnt: usize = 0;
-        let n = bytes.iter().rposition(|x| *x != 0).or(Some(0)).unwrap() + 1;
-        let v = bytes[0] + corr_add;
+        let n = bytes.iter().rposition(|x| *x != 0).unwrap_or(0) + 1;
+        let v = u16::from(bytes[0]) + corr_add;
         match n {
             1 if v == 0 => {
-                cnt += _await!(writer.write(&[0]))?;
+                _await!(writer.write_all(&[0]))?;
+                cnt += 1;
             }
             1 if v <= threshold => {
-                cnt += _await!(writer.write(&[mask1 | (v - corr_sub)]))?;
+                _await!(writer.write_all(&[mask1 | (v as u8 - corr_sub)]))?;
+                cnt += 1;
             }
             _ => {
                 let v: u8 = n.try_into().unwrap();
-                cnt += _await!(writer.write(&[mask2 | (v - 1)]))?;
-                cnt += _await!(writer.write(&bytes[..n]))?;
+                _await!(writer.write_all(&[mask2 | (v - 1)]))?;
+                _await!(writer.write_all(&bytes[..n]))?;
+                cnt += 1 + n;
             }
         }
         Ok(cnt)
@@ -169,62 +327,119 @@ impl VarIntLen {
 
 //---------Reader/Writer----------------
 
+/// Source of bytes for the `Decoder`.
+///
+/// Implementors must fill the whole buffer or fail. The async variant defaults to the
+/// blocking `read_exact`; non-blocking readers should override it.
+#[send_variant(Self: Send)]
 pub trait Reader {
     type Error;
-    #[_async]
-    fn read(&mut self, _bytes: &mut [u8]) -> Result<usize, Self::Error> {
-        unimplemented!();
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error>;
+    /// Whether the error means that the input has ended. `FramedReader` uses it to tell the
+    /// end of a stream from a truncated frame; readers that can't tell return `false`.
+    fn is_eof(_error: &Self::Error) -> bool {
+        false
+    }
+    async fn read_exact_async(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_exact(bytes)
     }
 }
 
+/// Sink of bytes for the `Encoder`.
+///
+/// Implementors must write the whole buffer or fail. The async variants default to the
+/// blocking `write_all` and `flush`; non-blocking writers should override them.
+#[send_variant(Self: Send)]
 pub trait Writer {
     type Error;
-    #[_async]
-    fn write(&mut self, _bytes: &[u8]) -> Result<usize, Self::Error> {
-        unimplemented!();
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+    fn flush(&mut self) -> Result<(), Self::Error>;
+    async fn write_all_async(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.write_all(bytes)
+    }
+    async fn flush_async(&mut self) -> Result<(), Self::Error> {
+        self.flush()
     }
 }
 
-impl<T: std::io::Read> Reader for std::io::BufReader<T> {
+#[send_variant(T: Send)]
+impl<T: std::io::Read> Reader for T {
     type Error = std::io::Error;
-    fn read(&mut self, bytes: &mut [u8]) -> Result<usize, Self::Error> {
-        let n = std::io::Read::read(self, bytes)?;
-        Ok(n)
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        std::io::Read::read_exact(self, bytes)
+    }
+    fn is_eof(error: &Self::Error) -> bool {
+        error.kind() == std::io::ErrorKind::UnexpectedEof
     }
 }
 
-impl<T: std::io::Write> Writer for std::io::BufWriter<T> {
+#[send_variant(T: Send)]
+impl<T: std::io::Write> Writer for T {
     type Error = std::io::Error;
-    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
-        let n = std::io::Write::write(self, bytes)?;
-        Ok(n)
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        std::io::Write::write_all(self, bytes)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        std::io::Write::flush(self)
     }
 }
 
-impl Reader for Vec<u8> {
-    type Error = ();
-    #[_async]
-    fn read(&mut self, bytes: &mut [u8]) -> Result<usize, Self::Error> {
-        bytes.copy_from_slice(&self[..bytes.len()]);
-        self.drain(..bytes.len());
-        Ok(bytes.len())
+/// Input ended before the requested number of bytes could be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnexpectedEof;
+
+impl Display for UnexpectedEof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unexpected end of input")
     }
 }
 
-impl Writer for Vec<u8> {
-    type Error = ();
-    #[_async]
-    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
-        self.extend_from_slice(bytes);
-        Ok(bytes.len())
+impl std::error::Error for UnexpectedEof {}
+
+/// Reads from a byte slice, advancing a position without copying or reallocating.
+#[derive(Debug, Clone)]
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// Unread part of the input.
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+}
+
+#[send_variant]
+impl<'a> Reader for SliceReader<'a> {
+    type Error = UnexpectedEof;
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let src = self.remaining().get(..bytes.len()).ok_or(UnexpectedEof)?;
+        bytes.copy_from_slice(src);
+        self.pos += bytes.len();
+        Ok(())
+    }
+    fn is_eof(_error: &Self::Error) -> bool {
+        true
     }
 }
 
 //-------Decoder----------------------
 
 macro_rules! fn_decode_uint {
-    ($ty:ty, $order: ident) => {
+    ($(#[$attr:meta])* $ty:ty, $order: ident) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<decode_ $ty>](&mut self) -> Result<$ty, Self::Error> {
                 let mut buf = [0_u8; std::mem::size_of::<$ty>()];
                 _await!(self.decode_uint(&mut buf))?;
@@ -234,8 +449,9 @@ macro_rules! fn_decode_uint {
     };
 }
 macro_rules! fn_decode_int {
-    ($ty:ty, $uty:ty, $order: ident) => {
+    ($(#[$attr:meta])* $ty:ty, $uty:ty, $order: ident) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<decode_ $ty>](&mut self) -> Result<$ty, Self::Error> {
                 let mut buf = [0_u8; std::mem::size_of::<$ty>()];
                 _await!(self.decode_uint(&mut buf))?;
@@ -246,15 +462,17 @@ macro_rules! fn_decode_int {
 }
 
 macro_rules! fn_decode_vec {
-    ($ty:ty, $closure: expr) => {
+    ($(#[$attr:meta])* $ty:ty, $closure: expr) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<decode_vec_ $ty>](&mut self, len: Option<usize>) -> Result<Vec<$ty>, Self::Error> {
-                let size = _await!(self.decode_bytes_begin(len.map(|x| x * std::mem::size_of::<$ty>())))?;
-                let len = size / std::mem::size_of::<$ty>();
-                if size != len * std::mem::size_of::<$ty>() {
-                    panic!("byte array size {} is not a multiple of element size {}", size, std::mem::size_of::<$ty>());
+                let elem_size = std::mem::size_of::<$ty>();
+                let size = _await!(self.decode_bytes_begin(len.map(|x| x * elem_size)))?;
+                let len = size / elem_size;
+                if size != len * elem_size {
+                    return Err(self.at(Error::BadArraySize { size, elem_size }));
                 }
-                let mut v = Vec::with_capacity(len);
+                let mut v = Vec::with_capacity(len.min(CHUNK_LEN / elem_size));
                 let mut buf = [0_u8; std::mem::size_of::<$ty>()];
                 for _i in 0..len {
                     _await!(self.decode_bytes_payload(&mut buf))?;
@@ -267,24 +485,270 @@ macro_rules! fn_decode_vec {
     };
 }
 
+/// Maximum number of bytes allocated ahead of reading them; longer buffers grow as the bytes
+/// arrive, so a bogus length can't allocate much more memory than the input has.
+pub(crate) const CHUNK_LEN: usize = 64 * 1024;
+
+/// Limits applied by the `Decoder` to untrusted input.
+///
+/// Every limit is checked before the memory for the corresponding element is allocated.
+/// The default configuration is unlimited, yet the memory allocated for a single element
+/// stays proportional to the bytes actually read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderConfig {
+    /// Maximum number of bytes read from the reader.
+    pub max_input_bytes: u64,
+    /// Maximum length of a single varbytes element (string, binary or packed array).
+    pub max_bytes_len: usize,
+    /// Maximum number of elements of a single varstruct (sequence, struct or tuple).
+    pub max_seq_len: usize,
+    /// Maximum nesting depth of sequences, structs and enums.
+    pub max_depth: usize,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: u64::MAX,
+            max_bytes_len: usize::MAX,
+            max_seq_len: usize::MAX,
+            max_depth: usize::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FrameKind {
+    Seq,
+    Struct,
+    Enum(u32),
+    Elem(usize),
+}
+
+/// Entry of the path stack; `name` is a range into `Decoder::names`.
+#[derive(Debug, Clone)]
+struct Frame {
+    kind: FrameKind,
+    name: std::ops::Range<usize>,
+}
+
 pub struct Decoder<R: Reader> {
     pub reader: R,
+    config: DecoderConfig,
+    pos: u64,
+    header_pos: u64,
+    depth: usize,
+    frames: Vec<Frame>,
+    names: String,
+}
+
+#[send_variant]
+impl<R: Reader> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_config(reader, DecoderConfig::default())
+    }
+
+    pub fn with_config(reader: R, config: DecoderConfig) -> Self {
+        Self {
+            reader,
+            config,
+            pos: 0,
+            header_pos: 0,
+            depth: 0,
+            frames: Vec::new(),
+            names: String::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DecoderConfig {
+        &self.config
+    }
+
+    /// Number of bytes consumed from the reader so far.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Path of the item being decoded, e.g. `LinkV2.content_id.<File>.0`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for (i, frame) in self.frames.iter().enumerate() {
+            let name = &self.names[frame.name.clone()];
+            let parent = i.checked_sub(1).map(|i| self.frames[i].kind);
+            match (frame.kind, parent) {
+                (FrameKind::Struct | FrameKind::Enum(_), None) => path.push_str(name),
+                (FrameKind::Struct, Some(FrameKind::Enum(tag))) => {
+                    if name.is_empty() {
+                        path.push_str(&format!(".<{}>", tag));
+                    } else {
+                        path.push_str(&format!(".<{}>", name));
+                    }
+                }
+                (FrameKind::Elem(index), Some(FrameKind::Seq)) => {
+                    path.push_str(&format!("[{}]", index));
+                }
+                (FrameKind::Elem(index), _) => {
+                    if name.is_empty() {
+                        path.push_str(&format!(".{}", index));
+                    } else {
+                        path.push('.');
+                        path.push_str(name);
+                    }
+                }
+                _ => {}
+            }
+        }
+        path
+    }
+
+    /// Decodes a value. Errors detected by the decoder or raised by `Decode` implementations
+    /// through `cerdito::Decoder::annotate` carry `Error::At` whatever the entry point; this
+    /// also annotates the other errors of `Decode` implementations.
+    #[_async]
+    pub fn decode<T: cerdito::Decode>(&mut self) -> Result<T, Error<R::Error>> {
+        self.depth = 0;
+        self.frames.clear();
+        self.names.clear();
+        _await!(T::decode(self)).map_err(|error| self.at(error))
+    }
+
+    /// Annotates an error with the position of the last header and the current path.
+    fn at(&self, error: Error<R::Error>) -> Error<R::Error> {
+        match error {
+            Error::At { .. } => error,
+            error => Error::At {
+                position: self.header_pos,
+                path: self.path(),
+                error: Box::new(error),
+            },
+        }
+    }
+
+    fn push_frame(&mut self, kind: FrameKind, name: Option<&str>) {
+        let start = self.names.len();
+        self.names.push_str(name.unwrap_or_default());
+        self.frames.push(Frame {
+            kind,
+            name: start..self.names.len(),
+        });
+    }
+
+    fn pop_frame(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            self.names.truncate(frame.name.start);
+        }
+    }
+
+    /// Number of input bytes that haven't been consumed yet, according to `max_input_bytes`.
+    fn input_budget(&self) -> u64 {
+        self.config.max_input_bytes - self.pos
+    }
+
+    fn consume(&mut self, n: usize) -> Result<(), Error<R::Error>> {
+        if n as u64 > self.input_budget() {
+            return Err(self.at(Error::LimitExceeded(Limit::InputBytes)));
+        }
+        self.pos += n as u64;
+        Ok(())
+    }
+
+    fn check_bytes_len<T: TryInto<usize>>(&self, size: T) -> Result<usize, Error<R::Error>> {
+        let size = usize_from(size).map_err(|e| self.at(e))?;
+        if size > self.config.max_bytes_len {
+            return Err(self.at(Error::LimitExceeded(Limit::BytesLen)));
+        }
+        if size as u64 > self.input_budget() {
+            return Err(self.at(Error::LimitExceeded(Limit::InputBytes)));
+        }
+        Ok(size)
+    }
+
+    fn check_seq_len<T: TryInto<usize>>(&self, len: T) -> Result<usize, Error<R::Error>> {
+        let len = usize_from(len).map_err(|e| self.at(e))?;
+        if len > self.config.max_seq_len {
+            return Err(self.at(Error::LimitExceeded(Limit::SeqLen)));
+        }
+        // every element takes at least one byte
+        if len as u64 > self.input_budget() {
+            return Err(self.at(Error::LimitExceeded(Limit::InputBytes)));
+        }
+        Ok(len)
+    }
+
+    fn enter(&mut self) -> Result<(), Error<R::Error>> {
+        if self.depth >= self.config.max_depth {
+            return Err(self.at(Error::LimitExceeded(Limit::Depth)));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    #[_async]
+    fn read_header(&mut self) -> Result<VarIntLen, Error<R::Error>> {
+        self.header_pos = self.pos;
+        let (v, n) =
+            _await!(VarIntLen::from_reader(&mut self.reader)).map_err(|e| self.at(Error::Io(e)))?;
+        self.consume(n)?;
+        Ok(v)
+    }
+
+    #[_async]
+    fn read_payload(&mut self, buf: &mut [u8]) -> Result<(), Error<R::Error>> {
+        self.consume(buf.len())?;
+        _await!(self.reader.read_exact(buf)).map_err(|e| self.at(Error::Io(e)))
+    }
+
+    #[_async]
+    fn read_payload_vec(&mut self, size: usize) -> Result<Vec<u8>, Error<R::Error>> {
+        let mut buf = Vec::new();
+        while buf.len() < size {
+            let start = buf.len();
+            buf.resize(start + (size - start).min(CHUNK_LEN), 0);
+            _await!(self.read_payload(&mut buf[start..]))?;
+        }
+        Ok(buf)
+    }
+
+    #[_async]
+    fn skip_payload(&mut self, size: usize) -> Result<(), Error<R::Error>> {
+        let mut buf = [0_u8; 256];
+        let mut left = size;
+        while left > 0 {
+            let n = left.min(buf.len());
+            _await!(self.read_payload(&mut buf[..n]))?;
+            left -= n;
+        }
+        Ok(())
+    }
+}
+
+fn char_from_u32<E>(v: u32) -> Result<char, Error<E>> {
+    char::from_u32(v).ok_or(Error::InvalidChar(v))
+}
+
+pub(crate) fn usize_from<T: TryInto<usize>, E>(v: T) -> Result<usize, Error<E>> {
+    v.try_into().map_err(|_| Error::LengthOverflow)
 }
 
+#[send_variant]
 impl<R: Reader> cerdito::Decoder for Decoder<R> {
-    type Error = R::Error;
+    type Error = Error<R::Error>;
+
+    fn annotate(&self, error: Self::Error) -> Self::Error {
+        self.at(error)
+    }
 
     #[_async]
     fn decode_bool(&mut self) -> Result<bool, Self::Error> {
-        Ok(if _await!(self.decode_u8())? != 0 {
-            true
-        } else {
-            false
-        })
+        Ok(_await!(self.decode_u8())? != 0)
     }
     #[_async]
     fn decode_char(&mut self) -> Result<char, Self::Error> {
-        Ok(char::from_u32(_await!(self.decode_u32())?).unwrap())
+        char_from_u32(_await!(self.decode_u32())?).map_err(|e| self.at(e))
     }
     fn_decode_uint! {u8, le}
     fn_decode_uint! {u16, le}
@@ -296,18 +760,30 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
     fn_decode_int! {i32, u32, le}
     fn_decode_int! {i64, u64, le}
     fn_decode_int! {i128, u128, le}
+    #[_async]
+    fn decode_usize(&mut self) -> Result<usize, Self::Error> {
+        _await!(self.decode_u64())?
+            .try_into()
+            .map_err(|_| self.at(Error::IntegerOverflow))
+    }
+    #[_async]
+    fn decode_isize(&mut self) -> Result<isize, Self::Error> {
+        _await!(self.decode_i64())?
+            .try_into()
+            .map_err(|_| self.at(Error::IntegerOverflow))
+    }
     fn_decode_uint! {f32, be}
     fn_decode_uint! {f64, be}
 
     #[_async]
     fn decode_string(&mut self) -> Result<String, Self::Error> {
-        Ok(String::from_utf8(_await!(self.decode_binary(None))?).unwrap())
+        String::from_utf8(_await!(self.decode_binary(None))?)
+            .map_err(|e| self.at(Error::InvalidUtf8(e)))
     }
     #[_async]
     fn decode_binary(&mut self, size: Option<usize>) -> Result<Vec<u8>, Self::Error> {
         let size = _await!(self.decode_bytes_begin(size))?;
-        let mut buf = vec![0_u8; size];
-        _await!(self.decode_bytes_payload(&mut buf))?;
+        let buf = _await!(self.read_payload_vec(size))?;
         _await!(self.decode_bytes_end())?;
         Ok(buf)
     }
@@ -316,46 +792,66 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
     fn decode_vec_u8(&mut self, len: Option<usize>) -> Result<Vec<u8>, Self::Error> {
         _await!(self.decode_binary(len))
     }
-    fn_decode_vec! {bool, |buf| if u8::from_le_bytes(buf) != 0 {true} else {false}}
-    fn_decode_vec! {char, |buf| char::from_u32(u32::from_le_bytes(buf)).unwrap()}
-    fn_decode_vec! {u16, |buf| u16::from_le_bytes(buf)}
-    fn_decode_vec! {u32, |buf| u32::from_le_bytes(buf)}
-    fn_decode_vec! {u64, |buf| u64::from_le_bytes(buf)}
-    fn_decode_vec! {u128, |buf| u128::from_le_bytes(buf)}
-    fn_decode_vec! {i8, |buf| i8::from_le_bytes(buf)}
-    fn_decode_vec! {i16, |buf| i16::from_le_bytes(buf)}
-    fn_decode_vec! {i32, |buf| i32::from_le_bytes(buf)}
-    fn_decode_vec! {i64, |buf| i64::from_le_bytes(buf)}
-    fn_decode_vec! {i128, |buf| i128::from_le_bytes(buf)}
-    fn_decode_vec! {f32, |buf| f32::from_le_bytes(buf)}
-    fn_decode_vec! {f64, |buf| f64::from_le_bytes(buf)}
+    fn_decode_vec! {bool, |buf| u8::from_le_bytes(buf) != 0}
+    #[_async]
+    fn decode_vec_char(&mut self, len: Option<usize>) -> Result<Vec<char>, Self::Error> {
+        _await!(self.decode_vec_u32(len))?
+            .into_iter()
+            .map(char_from_u32)
+            .collect::<Result<_, _>>()
+            .map_err(|e| self.at(e))
+    }
+    fn_decode_vec! {u16, u16::from_le_bytes}
+    fn_decode_vec! {u32, u32::from_le_bytes}
+    fn_decode_vec! {u64, u64::from_le_bytes}
+    fn_decode_vec! {u128, u128::from_le_bytes}
+    fn_decode_vec! {i8, i8::from_le_bytes}
+    fn_decode_vec! {i16, i16::from_le_bytes}
+    fn_decode_vec! {i32, i32::from_le_bytes}
+    fn_decode_vec! {i64, i64::from_le_bytes}
+    fn_decode_vec! {i128, i128::from_le_bytes}
+    fn_decode_vec! {f32, f32::from_le_bytes}
+    fn_decode_vec! {f64, f64::from_le_bytes}
 
     #[_async]
     fn decode_seq_begin(&mut self, _len: Option<usize>) -> Result<usize, Self::Error> {
-        let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-        match v {
-            VarIntLen::StructLen(buf) => Ok(u32::from_le_bytes(buf).try_into().unwrap()),
-            VarIntLen::Zero => Ok(0),
-            _ => panic!("bad seq header"),
-        }
+        let len = match _await!(self.read_header())? {
+            VarIntLen::StructLen(buf) => self.check_seq_len(u32::from_le_bytes(buf))?,
+            VarIntLen::Zero => 0,
+            _ => return Err(self.at(Error::BadSeqHeader)),
+        };
+        self.enter()?;
+        self.push_frame(FrameKind::Seq, None);
+        Ok(len)
     }
     #[_async]
     fn decode_seq_end(&mut self) -> Result<(), Self::Error> {
+        self.pop_frame();
+        self.leave();
         Ok(())
     }
 
     #[_async]
-    fn decode_enum_begin(&mut self, _enum_name: &str) -> Result<(u32, usize), Self::Error> {
-        let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-        match v {
-            VarIntLen::EnumTag(buf) => Ok((u32::from_le_bytes(buf), 1)),
-            VarIntLen::Value(buf) => Ok((u32::from_le_bytes(buf[..4].try_into().unwrap()), 0)),
-            VarIntLen::Zero => Ok((0, 0)),
-            _ => panic!("bad varenum header"),
-        }
+    fn decode_enum_begin(&mut self, enum_name: &str) -> Result<(u32, usize), Self::Error> {
+        let v = match _await!(self.read_header())? {
+            VarIntLen::EnumTag(buf) => (u32::from_le_bytes(buf), 1),
+            VarIntLen::Value(buf) => {
+                if buf[4..].iter().any(|x| *x != 0) {
+                    return Err(self.at(Error::BadEnumHeader));
+                }
+                (u32::from_le_bytes(buf[..4].try_into().unwrap()), 0)
+            }
+            VarIntLen::Zero => (0, 0),
+            _ => return Err(self.at(Error::BadEnumHeader)),
+        };
+        self.enter()?;
+        self.push_frame(FrameKind::Enum(v.0), Some(enum_name));
+        Ok(v)
     }
     #[_async]
     fn decode_enum_end(&mut self) -> Result<(), Self::Error> {
+        self.pop_frame();
+        self.leave();
         Ok(())
     }
 
@@ -363,32 +859,51 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
     fn decode_struct_begin(
         &mut self,
         _len: usize,
-        _struct_name: Option<&str>,
+        struct_name: Option<&str>,
     ) -> Result<usize, Self::Error> {
-        let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-        match v {
-            VarIntLen::StructLen(buf) => Ok(u32::from_le_bytes(buf).try_into().unwrap()),
-            VarIntLen::Zero => Ok(0),
-            _ => panic!("bad varstruct header"),
-        }
+        let len = match _await!(self.read_header())? {
+            VarIntLen::StructLen(buf) => self.check_seq_len(u32::from_le_bytes(buf))?,
+            VarIntLen::Zero => 0,
+            _ => return Err(self.at(Error::BadStructHeader)),
+        };
+        self.enter()?;
+        self.push_frame(FrameKind::Struct, struct_name);
+        Ok(len)
     }
     #[_async]
     fn decode_struct_end(&mut self) -> Result<(), Self::Error> {
+        self.pop_frame();
+        self.leave();
+        Ok(())
+    }
+
+    #[_async]
+    fn decode_elem_begin(
+        &mut self,
+        index: usize,
+        elem_name: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        self.push_frame(FrameKind::Elem(index), elem_name);
+        Ok(())
+    }
+    #[_async]
+    fn decode_elem_end(&mut self) -> Result<(), Self::Error> {
+        self.pop_frame();
         Ok(())
     }
 
     #[_async]
     fn decode_bytes_begin(&mut self, _size: Option<usize>) -> Result<usize, Self::Error> {
-        let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-        match v {
-            VarIntLen::ByteSize(buf) => Ok(u64::from_le_bytes(buf).try_into().unwrap()),
+        match _await!(self.read_header())? {
+            VarIntLen::ByteSize(buf) => self.check_bytes_len(u64::from_le_bytes(buf)),
             VarIntLen::Zero => Ok(0),
-            _ => panic!("bad varbyte header"),
+            _ => Err(self.at(Error::BadBytesHeader)),
         }
     }
     #[_async]
     fn decode_bytes_payload(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        _await!(self.reader.read(buf))
+        _await!(self.read_payload(buf))?;
+        Ok(buf.len())
     }
     #[_async]
     fn decode_bytes_end(&mut self) -> Result<(), Self::Error> {
@@ -397,17 +912,19 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
 
     #[_async]
     fn decode_uint(&mut self, bytes: &mut [u8]) -> Result<usize, Self::Error> {
-        let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-        match v {
+        match _await!(self.read_header())? {
             VarIntLen::Zero => {
                 bytes.fill(0);
                 Ok(bytes.len())
             }
             VarIntLen::Value(buf) => {
+                if buf[bytes.len()..].iter().any(|x| *x != 0) {
+                    return Err(self.at(Error::IntegerOverflow));
+                }
                 bytes.copy_from_slice(&buf[..bytes.len()]);
                 Ok(bytes.len())
             }
-            _ => panic!("bad varint header: {:?}", v),
+            _ => Err(self.at(Error::BadVarIntHeader)),
         }
     }
 
@@ -417,16 +934,18 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
 
         while counter != 0 {
             counter -= 1;
-            let (v, _n) = _await!(VarIntLen::from_reader(&mut self.reader))?;
-            match v {
+            // bound first, so that the temporary result isn't held across the awaits below
+            let header = _await!(self.read_header())?;
+            match header {
                 VarIntLen::ByteSize(buf) => {
-                    let size = u64::from_le_bytes(buf).try_into().unwrap();
-                    let mut buf = vec![0_u8; size];
-                    _await!(self.decode_bytes_payload(&mut buf))?;
+                    let size = self.check_bytes_len(u64::from_le_bytes(buf))?;
+                    _await!(self.skip_payload(size))?;
                 }
                 VarIntLen::StructLen(buf) => {
-                    let len: usize = u32::from_le_bytes(buf).try_into().unwrap();
-                    counter += len;
+                    let len = self.check_seq_len(u32::from_le_bytes(buf))?;
+                    counter = counter
+                        .checked_add(len)
+                        .ok_or_else(|| self.at(Error::LengthOverflow))?;
                 }
                 VarIntLen::EnumTag(_buf) => {
                     counter += 1;
@@ -441,8 +960,9 @@ impl<R: Reader> cerdito::Decoder for Decoder<R> {
 //--------Encoder----------------
 
 macro_rules! fn_encode_vec {
-    ($ty:ty) => {
+    ($(#[$attr:meta])* $ty:ty) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<encode_vec_ $ty>](&mut self, values: &[$ty]) -> Result<(), Self::Error> {
                 _await!(self.encode_bytes_begin(values.len() * std::mem::size_of::<$ty>()))?;
                 for value in values {
@@ -454,8 +974,9 @@ macro_rules! fn_encode_vec {
     };
 }
 macro_rules! fn_encode_uint {
-    ($ty:ty, $order: ident) => {
+    ($(#[$attr:meta])* $ty:ty, $order: ident) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<encode_ $ty>](&mut self, value: &$ty) -> Result<(), Self::Error> {
                 _await!(self.encode_uint(&value.[<to_ $order _bytes>]()))
             }
@@ -463,8 +984,9 @@ macro_rules! fn_encode_uint {
     };
 }
 macro_rules! fn_encode_int {
-    ($ty:ty, $order: ident) => {
+    ($(#[$attr:meta])* $ty:ty, $order: ident) => {
         paste::item! {
+            $(#[$attr])*
             #[_async] fn [<encode_ $ty>](&mut self, value: &$ty) -> Result<(), Self::Error> {
                 _await!(self.encode_uint(&ZigZag::encode(*value).[<to_ $order _bytes>]()))
             }
@@ -476,8 +998,25 @@ pub struct Encoder<W: Writer> {
     pub writer: W,
 }
 
+#[send_variant]
+impl<W: Writer> Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+#[send_variant]
+impl<W: Writer> Encoder<W> {
+    #[_async]
+    fn write_header(&mut self, v: VarIntLen) -> Result<(), Error<W::Error>> {
+        _await!(v.write(&mut self.writer)).map_err(Error::Io)?;
+        Ok(())
+    }
+}
+
+#[send_variant]
 impl<W: Writer> cerdito::Encoder for Encoder<W> {
-    type Error = W::Error;
+    type Error = Error<W::Error>;
 
     #[_async]
     fn encode_bool(&mut self, value: &bool) -> Result<(), Self::Error> {
@@ -520,7 +1059,7 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
 
     #[_async]
     fn encode_vec_bool(&mut self, values: &[bool]) -> Result<(), Self::Error> {
-        _await!(self.encode_bytes_begin(values.len() * std::mem::size_of::<bool>()))?;
+        _await!(self.encode_bytes_begin(std::mem::size_of_val(values)))?;
         for value in values {
             _await!(self.encode_bytes_payload(&[(*value).into()]))?;
         }
@@ -528,7 +1067,7 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
     }
     #[_async]
     fn encode_vec_char(&mut self, values: &[char]) -> Result<(), Self::Error> {
-        _await!(self.encode_bytes_begin(values.len() * std::mem::size_of::<char>()))?;
+        _await!(self.encode_bytes_begin(std::mem::size_of_val(values)))?;
         for value in values {
             let value: u32 = (*value).into();
             _await!(self.encode_bytes_payload(&value.to_le_bytes()))?;
@@ -571,9 +1110,8 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
 
     #[_async]
     fn encode_seq_begin(&mut self, len: usize) -> Result<(), Self::Error> {
-        let v = VarIntLen::from_struct_len(len.try_into().unwrap());
-        _await!(v.write(&mut self.writer))?;
-        Ok(())
+        let len = len.try_into().map_err(|_| Error::LengthOverflow)?;
+        _await!(self.write_header(VarIntLen::from_struct_len(len)))
     }
     #[_async]
     fn encode_seq_end(&mut self) -> Result<(), Self::Error> {
@@ -591,10 +1129,14 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
         let v = match len {
             0 => VarIntLen::from_value_u32(enum_tag),
             1 => VarIntLen::from_enum_tag(enum_tag),
-            _ => unreachable!(),
+            _ => {
+                return Err(Error::InvalidLength {
+                    expected: 1,
+                    got: len,
+                })
+            }
         };
-        _await!(v.write(&mut self.writer))?;
-        Ok(())
+        _await!(self.write_header(v))
     }
     #[_async]
     fn encode_enum_end(&mut self) -> Result<(), Self::Error> {
@@ -607,9 +1149,8 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
         len: usize,
         _struct_name: Option<&str>,
     ) -> Result<(), Self::Error> {
-        let v = VarIntLen::from_struct_len(len.try_into().unwrap());
-        _await!(v.write(&mut self.writer))?;
-        Ok(())
+        let len = len.try_into().map_err(|_| Error::LengthOverflow)?;
+        _await!(self.write_header(VarIntLen::from_struct_len(len)))
     }
     #[_async]
     fn encode_struct_end(&mut self) -> Result<(), Self::Error> {
@@ -618,13 +1159,12 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
 
     #[_async]
     fn encode_bytes_begin(&mut self, size: usize) -> Result<(), Self::Error> {
-        let v = VarIntLen::from_byte_size(size.try_into().unwrap());
-        _await!(v.write(&mut self.writer))?;
-        Ok(())
+        let size = size.try_into().map_err(|_| Error::LengthOverflow)?;
+        _await!(self.write_header(VarIntLen::from_byte_size(size)))
     }
     #[_async]
     fn encode_bytes_payload(&mut self, value: &[u8]) -> Result<(), Self::Error> {
-        _await!(self.writer.write(value))?;
+        _await!(self.writer.write_all(value)).map_err(Error::Io)?;
         Ok(())
     }
     #[_async]
@@ -634,9 +1174,7 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
 
     #[_async]
     fn encode_uint(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
-        let v = VarIntLen::from_value_slice(bytes);
-        _await!(v.write(&mut self.writer))?;
-        Ok(())
+        _await!(self.write_header(VarIntLen::from_value_slice(bytes)))
     }
 
     #[_async]
@@ -655,13 +1193,37 @@ impl<W: Writer> cerdito::Encoder for Encoder<W> {
 }
 
 #[cfg(test)]
-mod tests {
+#[allow(clippy::identity_op)]
+pub(crate) mod tests {
     use super::*;
+    use cerdito::{Decode, Encode, SendDecode, SendEncode};
+
+    pub(crate) fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+        let mut encoder = Encoder::new(Vec::new());
+        value.encode(&mut encoder).unwrap();
+        encoder.writer
+    }
+
+    pub(crate) fn decode<T: Decode>(bytes: &[u8]) -> Result<T, Error<UnexpectedEof>> {
+        T::decode(&mut Decoder::new(SliceReader::new(bytes)))
+    }
+
+    /// Decodes a value from `reader` on a task of a multi-threaded runtime.
+    pub(crate) async fn decode_send<T, R>(reader: R) -> T
+    where
+        T: SendDecode + 'static,
+        R: SendReader + 'static,
+        R::Error: Debug,
+    {
+        let task =
+            tokio::spawn(async move { T::decode_send(&mut Decoder::new(reader)).await.unwrap() });
+        task.await.unwrap()
+    }
 
     fn varintlen_write_read(v: VarIntLen) -> VarIntLen {
-        let mut rw: Vec<u8> = Vec::new();
-        v.write(&mut rw).unwrap();
-        let (v2, _n) = VarIntLen::from_reader(&mut rw).unwrap();
+        let mut w: Vec<u8> = Vec::new();
+        v.write(&mut w).unwrap();
+        let (v2, _n) = VarIntLen::from_reader(&mut SliceReader::new(&w)).unwrap();
         v2
     }
 
@@ -777,7 +1339,7 @@ mod tests {
 
     #[test]
     fn test_varintlen_read() {
-        let mut r: Vec<u8> = vec![
+        let bytes: Vec<u8> = vec![
             M_VALUE | 0_u8,
             M_STRUCT | 0_u8,
             M_STRUCT | 6_u8,
@@ -849,6 +1411,7 @@ mod tests {
             0xff,
             0x00,
         ];
+        let mut r = SliceReader::new(&bytes);
 
         let (v, _n) = VarIntLen::from_reader(&mut r).unwrap();
         assert_eq!(v, VarIntLen::Zero);
@@ -921,4 +1484,566 @@ mod tests {
         let (v, _n) = VarIntLen::from_reader(&mut r).unwrap();
         assert_eq!(v, VarIntLen::Zero);
     }
+
+    #[test]
+    fn test_varintlen_write_read() {
+        for v in [
+            VarIntLen::new(),
+            VarIntLen::from_value_u32(95),
+            VarIntLen::from_value_u128(u128::MAX),
+            VarIntLen::from_byte_size(64),
+            VarIntLen::from_byte_size(u64::MAX),
+            VarIntLen::from_struct_len(32),
+            VarIntLen::from_struct_len(u32::MAX),
+            VarIntLen::from_enum_tag(0),
+            VarIntLen::from_enum_tag(31),
+            VarIntLen::from_enum_tag(200),
+            VarIntLen::from_enum_tag(u32::MAX),
+        ] {
+            assert_eq!(varintlen_write_read(v), v);
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        fn decoder(bytes: &[u8]) -> Decoder<SliceReader<'_>> {
+            Decoder::new(SliceReader::new(bytes))
+        }
+
+        let r = Vec::<u8>::decode(&mut decoder(&[M_BYTES | 0]));
+        assert!(matches!(r.unwrap_err().root(), Error::BadSeqHeader));
+        let r = <(u8,)>::decode(&mut decoder(&[1]));
+        assert!(matches!(r.unwrap_err().root(), Error::BadStructHeader));
+        let r = Option::<u8>::decode(&mut decoder(&[M_STRUCT | 0]));
+        assert!(matches!(r.unwrap_err().root(), Error::BadEnumHeader));
+        let r = String::decode(&mut decoder(&[M_STRUCT | 0]));
+        assert!(matches!(r.unwrap_err().root(), Error::BadBytesHeader));
+        let r = u8::decode(&mut decoder(&[M_BYTES | 0, 1]));
+        assert!(matches!(r.unwrap_err().root(), Error::BadVarIntHeader));
+        let r = u8::decode(&mut decoder(&[M_VALUE_LEN | 1, 0, 1]));
+        assert!(matches!(r.unwrap_err().root(), Error::IntegerOverflow));
+        let r = String::decode(&mut decoder(&[M_BYTES | 1, 0xc3, 0x28]));
+        assert!(matches!(r.unwrap_err().root(), Error::InvalidUtf8(_)));
+        let r = char::decode(&mut decoder(&[M_VALUE_LEN | 1, 0x00, 0xd8]));
+        assert!(matches!(r.unwrap_err().root(), Error::InvalidChar(0xd800)));
+
+        let r = Option::<u8>::decode(&mut decoder(&[2]));
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::UnknownVariant { tag: 2, .. }
+        ));
+        // `Some` without a payload, as a unit variant or with an empty variant struct
+        let r = Option::<u8>::decode(&mut decoder(&[1]));
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::InvalidLength {
+                expected: 1,
+                got: 0
+            }
+        ));
+        let mut encoder = Encoder::new(Vec::new());
+        cerdito::Encoder::encode_enum_begin(&mut encoder, 1, 1, "Option", "Some").unwrap();
+        cerdito::Encoder::encode_struct_begin(&mut encoder, 0, None).unwrap();
+        let r = Option::<u8>::decode(&mut decoder(&encoder.writer));
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::InvalidLength {
+                expected: 1,
+                got: 0
+            }
+        ));
+        let r = cerdito::ByteArr::<2>::decode(&mut decoder(&[M_BYTES | 0, 1]));
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::InvalidLength {
+                expected: 2,
+                got: 1
+            }
+        ));
+        let r = <[u8; 2]>::decode(&mut decoder(&[M_STRUCT | 0, 1]));
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::InvalidLength {
+                expected: 2,
+                got: 1
+            }
+        ));
+
+        let mut d = decoder(&[M_BYTES | 2, 0, 0, 0]);
+        let r = cerdito::Decoder::decode_vec_u16(&mut d, None);
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::BadArraySize {
+                size: 3,
+                elem_size: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn test_slice_reader() {
+        let mut encoder = Encoder::new(Vec::new());
+        String::from("hello").encode(&mut encoder).unwrap();
+        (1_u32, 2_u64).encode(&mut encoder).unwrap();
+
+        let mut decoder = Decoder::new(SliceReader::new(&encoder.writer));
+        assert_eq!(String::decode(&mut decoder).unwrap(), "hello");
+        assert_eq!(decoder.reader.position(), 6);
+        assert_eq!(decoder.reader.remaining(), &[M_STRUCT | 1, 1, 2]);
+        assert_eq!(<(u32, u64)>::decode(&mut decoder).unwrap(), (1, 2));
+        assert!(decoder.reader.is_empty());
+
+        let mut decoder = Decoder::new(SliceReader::new(&encoder.writer[..4]));
+        let r = String::decode(&mut decoder);
+        assert!(matches!(r.unwrap_err().root(), Error::Io(UnexpectedEof)));
+        let mut decoder = Decoder::new(SliceReader::new(&[]));
+        let r = u8::decode(&mut decoder);
+        assert!(matches!(r.unwrap_err().root(), Error::Io(UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_short_reads_and_writes() {
+        // Transfers at most one byte per call, like a slow socket.
+        struct Trickle<T>(T);
+        impl<T: std::io::Read> std::io::Read for Trickle<T> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(1);
+                self.0.read(&mut buf[..n])
+            }
+        }
+        impl<T: std::io::Write> std::io::Write for Trickle<T> {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(1);
+                self.0.write(&buf[..n])
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.0.flush()
+            }
+        }
+
+        let value = (String::from("hello, world!"), (u64::MAX, vec![1_i32, -1]));
+        let mut encoder = Encoder::new(Trickle(Vec::new()));
+        value.encode(&mut encoder).unwrap();
+        Writer::flush(&mut encoder.writer).unwrap();
+
+        let bytes = encoder.writer.0;
+        let mut decoder = Decoder::new(Trickle(&bytes[..]));
+        assert_eq!(
+            <(String, (u64, Vec<i32>))>::decode(&mut decoder).unwrap(),
+            value
+        );
+
+        let mut decoder = Decoder::new(&bytes[..bytes.len() - 1]);
+        match <(String, (u64, Vec<i32>))>::decode(&mut decoder) {
+            Err(e) => match e.root() {
+                Error::Io(e) => assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof),
+                e => panic!("unexpected error {:?}", e),
+            },
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_decoder_limits() {
+        fn decode<T: Decode>(
+            bytes: &[u8],
+            config: DecoderConfig,
+        ) -> Result<T, Error<UnexpectedEof>> {
+            T::decode(&mut Decoder::with_config(SliceReader::new(bytes), config))
+        }
+
+        let bytes = encode(&(String::from("hello"), vec![vec![1_u8, 2], vec![3]]));
+        type T = (String, Vec<Vec<u8>>);
+
+        let config = DecoderConfig {
+            max_input_bytes: bytes.len() as u64,
+            max_bytes_len: 5,
+            max_seq_len: 2,
+            max_depth: 3,
+        };
+        assert!(decode::<T>(&bytes, config).is_ok());
+
+        let r = decode::<T>(
+            &bytes,
+            DecoderConfig {
+                max_input_bytes: 8,
+                ..config
+            },
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::InputBytes)
+        ));
+        let r = decode::<T>(
+            &bytes,
+            DecoderConfig {
+                max_bytes_len: 4,
+                ..config
+            },
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::BytesLen)
+        ));
+        let r = decode::<T>(
+            &bytes,
+            DecoderConfig {
+                max_seq_len: 1,
+                ..config
+            },
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::SeqLen)
+        ));
+        let r = decode::<T>(
+            &bytes,
+            DecoderConfig {
+                max_depth: 2,
+                ..config
+            },
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::Depth)
+        ));
+
+        // claimed lengths larger than the input are rejected before allocation
+        let config = DecoderConfig {
+            max_input_bytes: 16,
+            ..DecoderConfig::default()
+        };
+        let r = decode::<cerdito::ByteVec>(
+            &[
+                M_BYTES_LEN | 7,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+                0xff,
+            ],
+            config,
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::InputBytes)
+        ));
+        let r = decode::<Vec<u64>>(&[M_STRUCT_LEN | 3, 0xff, 0xff, 0xff, 0xff], config);
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::InputBytes)
+        ));
+        let r = decode::<()>(
+            &[M_STRUCT | 0, M_BYTES_LEN | 3, 0xff, 0xff, 0xff, 0xff],
+            config,
+        );
+        assert!(matches!(
+            r.unwrap_err().root(),
+            Error::LimitExceeded(Limit::InputBytes)
+        ));
+
+        // without limits, the buffers grow as the bytes arrive instead of being allocated
+        // for the claimed length upfront
+        let mut bytes = [
+            M_BYTES_LEN | 7,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0xff,
+            0x0f,
+        ];
+        let r = String::decode(&mut Decoder::new(SliceReader::new(&bytes)));
+        assert!(matches!(r.unwrap_err().root(), Error::Io(UnexpectedEof)));
+        bytes[1] = 0xf8;
+        let r = cerdito::VArr::<u64>::decode(&mut Decoder::new(SliceReader::new(&bytes)));
+        assert!(matches!(r.unwrap_err().root(), Error::Io(UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_decode_error_path() {
+        type T = (u8, Vec<Option<String>>);
+        let value: T = (7, vec![Some("a".to_string()), Some("bc".to_string())]);
+        let bytes = encode(&value);
+
+        let mut decoder = Decoder::new(SliceReader::new(&bytes));
+        assert_eq!(decoder.decode::<T>().unwrap(), value);
+        assert_eq!(decoder.position(), bytes.len() as u64);
+        assert_eq!(decoder.path(), "");
+
+        // truncated payload of the last string
+        let mut decoder = Decoder::new(SliceReader::new(&bytes[..bytes.len() - 1]));
+        let r = decoder.decode::<T>();
+        let Err(Error::At {
+            position,
+            path,
+            error,
+        }) = r
+        else {
+            panic!("unexpected result {:?}", r);
+        };
+        assert_eq!(position, bytes.len() as u64 - 3);
+        assert_eq!(path, ".1[1].<1>.0");
+        assert!(matches!(*error, Error::Io(UnexpectedEof)));
+
+        // errors detected by the decoder are annotated whatever the entry point
+        let mut decoder = Decoder::new(SliceReader::new(&bytes[..bytes.len() - 1]));
+        let error = T::decode(&mut decoder).unwrap_err();
+        assert!(matches!(error.root(), Error::Io(UnexpectedEof)));
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "i/o error: unexpected end of input at byte {} (.1[1].<1>.0)",
+                bytes.len() - 3
+            )
+        );
+        let mut decoder = Decoder::new(SliceReader::new(&bytes));
+        let error = cerdito::Decoder::decode_string(&mut decoder).unwrap_err();
+        assert_eq!(error.to_string(), "bad varbytes header at byte 0");
+
+        // unknown variant of the first option
+        let mut bytes = bytes;
+        bytes[3] = 2;
+        let r = Decoder::new(SliceReader::new(&bytes)).decode::<T>();
+        let error = r.unwrap_err();
+        assert!(matches!(error.root(), Error::UnknownVariant { tag: 2, .. }));
+        assert_eq!(
+            error.to_string(),
+            "enum Option doesn't support variant 2 at byte 3 (.1[0])"
+        );
+    }
+
+    #[test]
+    fn test_tuples() {
+        let bytes = encode(&(1_u8, String::from("a"), 2_u16));
+
+        // old program, new data
+        let mut decoder = Decoder::new(SliceReader::new(&bytes));
+        assert_eq!(
+            <(u8, String)>::decode(&mut decoder).unwrap(),
+            (1, String::from("a"))
+        );
+        assert!(decoder.reader.is_empty());
+
+        // new program, old data
+        let mut decoder = Decoder::new(SliceReader::new(&bytes));
+        assert_eq!(
+            <(u8, String, u16, Option<u8>)>::decode(&mut decoder).unwrap(),
+            (1, String::from("a"), 2, None)
+        );
+
+        type T32 = (
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8, //
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+            u8,
+        );
+        let mut value = decode::<T32>(&[0]).unwrap();
+        value.31 = 7;
+        let bytes = encode(&value);
+        assert_eq!(bytes.len(), 1 + 32);
+        let value = decode::<T32>(&bytes).unwrap();
+        assert_eq!((value.0, value.31), (0, 7));
+    }
+
+    #[test]
+    fn test_collections() {
+        use std::collections::{
+            BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque,
+        };
+
+        // maps are encoded as sequences of pairs
+        let pairs = vec![(String::from("a"), 1_u32), (String::from("b"), 2)];
+        let map: BTreeMap<_, _> = pairs.iter().cloned().collect();
+        assert_eq!(encode(&map), encode(&pairs));
+        assert_eq!(
+            decode::<BTreeMap<String, u32>>(&encode(&pairs)).unwrap(),
+            map
+        );
+        let map: HashMap<_, _> = pairs.iter().cloned().collect();
+        assert_eq!(decode::<HashMap<String, u32>>(&encode(&map)).unwrap(), map);
+
+        // extra pair elements are skipped, missing ones are an error
+        let triples = vec![(1_u8, 2_u8, 3_u8)];
+        let map = decode::<BTreeMap<u8, u8>>(&encode(&triples)).unwrap();
+        assert_eq!(map, BTreeMap::from([(1, 2)]));
+        let r = decode::<BTreeMap<u8, u8>>(&encode(&vec![(1_u8,)]));
+        let Err(Error::At {
+            position,
+            path,
+            error,
+        }) = r
+        else {
+            panic!("unexpected result {:?}", r);
+        };
+        assert_eq!((position, path.as_str()), (1, "[0]"));
+        assert!(matches!(
+            *error,
+            Error::InvalidLength {
+                expected: 2,
+                got: 1
+            }
+        ));
+
+        // sets and other sequences are encoded as `Vec<T>`
+        let values = vec![3_i64, 1, 2];
+        let bytes = encode(&values);
+        let set: HashSet<_> = values.iter().copied().collect();
+        assert_eq!(decode::<HashSet<i64>>(&encode(&set)).unwrap(), set);
+        let set: BTreeSet<_> = values.iter().copied().collect();
+        assert_eq!(decode::<BTreeSet<i64>>(&bytes).unwrap(), set);
+        let deque: VecDeque<_> = values.iter().copied().collect();
+        assert_eq!(encode(&deque), bytes);
+        assert_eq!(decode::<VecDeque<i64>>(&bytes).unwrap(), deque);
+        let list: LinkedList<_> = values.iter().copied().collect();
+        assert_eq!(encode(&list), bytes);
+        assert_eq!(decode::<LinkedList<i64>>(&bytes).unwrap(), list);
+        let heap = decode::<BinaryHeap<i64>>(&bytes).unwrap();
+        assert_eq!(heap.into_sorted_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_references_and_smart_pointers() {
+        use std::borrow::Cow;
+        use std::sync::Arc;
+
+        let string = String::from("hello");
+        let bytes = encode(&string);
+        assert_eq!(encode("hello"), bytes);
+        assert_eq!(encode(&&mut string.clone()), bytes);
+        assert_eq!(encode(&std::rc::Rc::new(string.clone())), bytes);
+        assert_eq!(encode(&Arc::<str>::from("hello")), bytes);
+        assert_eq!(encode(&Cow::Borrowed("hello")), bytes);
+        assert_eq!(&*decode::<Box<str>>(&bytes).unwrap(), "hello");
+        assert_eq!(*decode::<std::rc::Rc<String>>(&bytes).unwrap(), string);
+        assert_eq!(*decode::<Arc<String>>(&bytes).unwrap(), string);
+        assert_eq!(
+            decode::<Cow<'_, str>>(&bytes).unwrap(),
+            Cow::<str>::Owned(string)
+        );
+
+        let vec = vec![(1_u8, 'a'), (2, 'b')];
+        let bytes = encode(&vec);
+        assert_eq!(encode(&vec[..]), bytes);
+        assert_eq!(encode(&vec.iter().collect::<Vec<_>>()), bytes);
+        assert_eq!(encode(&vec.clone().into_boxed_slice()), bytes);
+        assert_eq!(
+            decode::<Box<[(u8, char)]>>(&bytes).unwrap(),
+            vec.clone().into_boxed_slice()
+        );
+        assert_eq!(decode::<Cow<'_, [(u8, char)]>>(&bytes).unwrap(), vec);
+    }
+
+    #[test]
+    fn test_usize_isize() {
+        // same wire representation as u64 and i64 on every platform
+        assert_eq!(encode(&300_usize), encode(&300_u64));
+        assert_eq!(encode(&-300_isize), encode(&-300_i64));
+        assert_eq!(encode(&usize::MAX), encode(&(usize::MAX as u64)));
+        assert_eq!(encode(&isize::MIN), encode(&(isize::MIN as i64)));
+        assert_eq!(decode::<usize>(&encode(&usize::MAX)).unwrap(), usize::MAX);
+        assert_eq!(decode::<isize>(&encode(&isize::MIN)).unwrap(), isize::MIN);
+        assert_eq!(decode::<usize>(&encode(&7_u8)).unwrap(), 7);
+
+        let r = decode::<usize>(&encode(&(u128::from(u64::MAX) + 1)));
+        assert!(matches!(r.unwrap_err().root(), Error::IntegerOverflow));
+        if usize::BITS < 64 {
+            let r = decode::<usize>(&encode(&u64::MAX));
+            assert!(matches!(r.unwrap_err().root(), Error::IntegerOverflow));
+            let r = decode::<isize>(&encode(&i64::MIN));
+            assert!(matches!(r.unwrap_err().root(), Error::IntegerOverflow));
+        }
+    }
+
+    #[test]
+    fn test_packed_arrays() {
+        use cerdito::{Arr, ByteVec, VArr};
+
+        // one varbytes element with fixed-width LE values
+        let arr = Arr([1_u32, 0x0100, u32::MAX]);
+        let bytes = encode(&arr);
+        assert_eq!(
+            bytes,
+            vec![M_BYTES | 11, 1, 0, 0, 0, 0, 1, 0, 0, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(decode::<Arr<u32, 3>>(&bytes).unwrap(), arr);
+        assert_eq!(decode::<VArr<u32>>(&bytes).unwrap(), VArr(arr.to_vec()));
+        let r = decode::<Arr<u32, 2>>(&bytes);
+        assert!(matches!(
+            r,
+            Err(Error::At { position: 0, ref error, .. }) if matches!(**error, Error::InvalidLength {
+                expected: 2,
+                got: 3
+            })
+        ));
+
+        let varr = VArr::from(vec![-1.5_f64, 2.0]);
+        let bytes = encode(&varr);
+        assert_eq!(bytes.len(), 1 + 16);
+        assert_eq!(decode::<VArr<f64>>(&bytes).unwrap(), varr);
+        assert_eq!(&*varr, &[-1.5, 2.0]);
+
+        let chars = Arr(['a', 'ж']);
+        assert_eq!(decode::<Arr<char, 2>>(&encode(&chars)).unwrap(), chars);
+        let bools = VArr(vec![true, false, true]);
+        assert_eq!(decode::<VArr<bool>>(&encode(&bools)).unwrap(), bools);
+        assert_eq!(
+            encode(&VArr(vec![1_u8, 2, 3])),
+            encode(&ByteVec(vec![1, 2, 3]))
+        );
+        assert_eq!(Arr::<i16, 4>::default(), Arr([0; 4]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_send_futures() {
+        // generic code can spawn the futures on a multi-threaded runtime
+        async fn encode_send<T: SendEncode + Send + 'static>(value: T) -> Vec<u8> {
+            let task = tokio::spawn(async move {
+                let mut encoder = Encoder::new(Vec::new());
+                value.encode_send(&mut encoder).await.unwrap();
+                encoder.writer
+            });
+            task.await.unwrap()
+        }
+
+        let value = || (String::from("a"), vec![Some(1_u32), None], [1_i8, -1]);
+        let bytes = encode_send(value()).await;
+        assert_eq!(bytes, encode(&value()));
+        let decoded: (String, Vec<Option<u32>>, [i8; 2]) =
+            decode_send(std::io::Cursor::new(bytes)).await;
+        assert_eq!(decoded, value());
+    }
 }